@@ -26,6 +26,18 @@ export default function SandBox({ universe }: SandBoxProps) {
   const [rotationIndicator, setRotationIndicator] = useState(0);
   const pixiContainerRef = useRef<any>(null);
 
+  // Where ball i's link starts: its chain's pivot (moving with the pivot motion or the
+  // cart) for the first ball of a chain, the ball it hangs from otherwise
+  const linkStart = (balls: Ball[], i: number) => {
+    const pivot = universe.get_ball_pivot(i);
+    if (pivot === undefined) {
+      return balls[universe.get_ball_parent(i)!].pos;
+    }
+    const start = { x: pivot.x, y: pivot.y };
+    pivot.free();
+    return start;
+  };
+
   const handleballDragStart = (index: number, event: any) => {
     event.stopPropagation();
     setSelectedballIndex(index);
@@ -41,9 +53,8 @@ export default function SandBox({ universe }: SandBoxProps) {
 
     const localPos = pixiContainerRef.current.toLocal(event.global);
 
-    // Get the position relative to the previous ball or origin
-    const prevX = index === 0 ? 0 : balls[index - 1].pos.x;
-    const prevY = index === 0 ? 0 : balls[index - 1].pos.y;
+    // Get the position relative to where the ball's link starts
+    const { x: prevX, y: prevY } = linkStart(balls, index);
 
     // Calculate new angle from mouse position
     const dx = localPos.x - prevX;
@@ -74,8 +85,7 @@ export default function SandBox({ universe }: SandBoxProps) {
       // Draw angle arcs, velocity and acceleration vectors when paused
       if (isPaused) {
         for (let i = 0; i < balls.length; i++) {
-          const prevX = i === 0 ? 0 : balls[i - 1].pos.x;
-          const prevY = i === 0 ? 0 : balls[i - 1].pos.y;
+          const { x: prevX, y: prevY } = linkStart(balls, i);
 
           // Draw angle arc
           const arcRadius = 40;
@@ -163,9 +173,12 @@ export default function SandBox({ universe }: SandBoxProps) {
         }
       }
 
-      // Draw center ball
-      graphics.circle(0, 0, 10);
-      graphics.fill({ color: 0x0f0f0f });
+      // Draw the pivots the chains hang from
+      for (const pivot of universe.get_chain_pivots()) {
+        graphics.circle(pivot.x, pivot.y, 10);
+        graphics.fill({ color: 0x0f0f0f });
+        pivot.free();
+      }
 
       // Draw rods
       for (let i = 0; i < balls.length; i++) {
@@ -175,10 +188,8 @@ export default function SandBox({ universe }: SandBoxProps) {
             color: balls[i].rod!.color,
             alpha: 1,
           });
-          graphics.moveTo(
-            i == 0 ? 0 : balls[i - 1].pos.x,
-            i == 0 ? 0 : balls[i - 1].pos.y
-          );
+          const start = linkStart(balls, i);
+          graphics.moveTo(start.x, start.y);
           graphics.lineTo(balls[i].pos.x, balls[i].pos.y);
           graphics.stroke();
         }
@@ -268,8 +279,7 @@ export default function SandBox({ universe }: SandBoxProps) {
 
           // Check if clicked on a rod
           for (let i = 0; i < balls.length; i++) {
-            const prevX = i === 0 ? 0 : balls[i - 1].pos.x;
-            const prevY = i === 0 ? 0 : balls[i - 1].pos.y;
+            const { x: prevX, y: prevY } = linkStart(balls, i);
             const ballX = balls[i].pos.x;
             const ballY = balls[i].pos.y;
 
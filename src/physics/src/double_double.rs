@@ -0,0 +1,147 @@
+use std::ops::{ Add, Div, Mul, Neg, Sub };
+use std::f64::consts;
+use serde::{ Serialize, Deserialize };
+use crate::scalar::Scalar;
+
+/// Unevaluated sum `hi + lo` of two floats with `|lo| <= ulp(hi) / 2`, about 32 significant
+/// digits with plain f64 hardware (Dekker, Knuth; the algorithms of the QD library).
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub struct DoubleDouble {
+    pub hi: f64,
+    pub lo: f64,
+}
+
+// pi / 2 to double-double precision
+const FRAC_PI_2: DoubleDouble = DoubleDouble { hi: consts::FRAC_PI_2, lo: 6.123233995736766e-17 };
+
+// a + b = s + e exactly
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let bb = s - a;
+    (s, a - (s - bb) + (b - bb))
+}
+
+// Same as two_sum, assuming |a| >= |b|
+fn quick_two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    (s, b - (s - a))
+}
+
+// a * b = p + e exactly
+fn two_prod(a: f64, b: f64) -> (f64, f64) {
+    let p = a * b;
+    (p, f64::mul_add(a, b, -p))
+}
+
+impl DoubleDouble {
+    pub fn new(hi: f64, lo: f64) -> Self {
+        let (hi, lo) = two_sum(hi, lo);
+        Self { hi, lo }
+    }
+
+    fn renormalized(hi: f64, lo: f64) -> Self {
+        let (hi, lo) = quick_two_sum(hi, lo);
+        Self { hi, lo }
+    }
+
+    pub fn abs(self) -> Self {
+        if self.hi < 0.0 { -self } else { self }
+    }
+}
+
+impl From<f64> for DoubleDouble {
+    fn from(value: f64) -> Self {
+        Self { hi: value, lo: 0.0 }
+    }
+}
+
+impl Add for DoubleDouble {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        let (s, e) = two_sum(self.hi, rhs.hi);
+        let (t, f) = two_sum(self.lo, rhs.lo);
+        let (s, e) = quick_two_sum(s, e + t);
+        Self::renormalized(s, e + f)
+    }
+}
+
+impl Sub for DoubleDouble {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        self + -rhs
+    }
+}
+
+impl Neg for DoubleDouble {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self { hi: -self.hi, lo: -self.lo }
+    }
+}
+
+impl Mul for DoubleDouble {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        let (p, e) = two_prod(self.hi, rhs.hi);
+        Self::renormalized(p, e + (self.hi * rhs.lo + self.lo * rhs.hi))
+    }
+}
+
+impl Div for DoubleDouble {
+    type Output = Self;
+    // Long division, one float digit at a time
+    fn div(self, rhs: Self) -> Self {
+        let q1 = self.hi / rhs.hi;
+        let r = self - rhs * Self::from(q1);
+        let q2 = r.hi / rhs.hi;
+        let r = r - rhs * Self::from(q2);
+        let q3 = r.hi / rhs.hi;
+        Self::renormalized(q1, q2) + Self::from(q3)
+    }
+}
+
+impl Scalar for DoubleDouble {
+    fn constant(value: f64) -> Self {
+        Self::from(value)
+    }
+
+    // Reduce to |r| <= pi/4 around the nearest multiple of pi/2, then sum both Taylor series
+    fn sin_cos(&self) -> (Self, Self) {
+        let quadrant = (self.hi / FRAC_PI_2.hi).round();
+        let r = *self - FRAC_PI_2 * Self::from(quadrant);
+        let r2 = r * r;
+
+        let (mut sin, mut cos) = (r, Self::from(1.0));
+        let (mut sin_term, mut cos_term) = (r, Self::from(1.0));
+        for k in 1..20 {
+            let k = k as f64;
+            sin_term = -sin_term * r2 / Self::from(2.0 * k * (2.0 * k + 1.0));
+            cos_term = -cos_term * r2 / Self::from((2.0 * k - 1.0) * (2.0 * k));
+            sin = sin + sin_term;
+            cos = cos + cos_term;
+            if cos_term.hi.abs() < 1e-34 {
+                break;
+            }
+        }
+
+        match (quadrant as i64).rem_euclid(4) {
+            0 => (sin, cos),
+            1 => (cos, -sin),
+            2 => (-sin, -cos),
+            _ => (-cos, sin),
+        }
+    }
+
+    // One Newton step from the float square root doubles its digits
+    fn sqrt(&self) -> Self {
+        if self.hi <= 0.0 {
+            return Self::from(f64::sqrt(self.hi));
+        }
+        let x = Self::from(f64::sqrt(self.hi));
+        x + (*self - x * x) / (x * Self::from(2.0))
+    }
+
+    fn value(&self) -> f64 {
+        self.hi + self.lo
+    }
+}
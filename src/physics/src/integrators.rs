@@ -0,0 +1,1369 @@
+use nalgebra::{ DMatrix, DVector, LU };
+use std::collections::VecDeque;
+use wasm_bindgen::prelude::*;
+use crate::series::{ largest_coefficient, taylor_coefficients, taylor_step_size, Series };
+
+/// Right-hand side `f(t, y)` of the system `y' = f(t, y)` an [`Integrator`] advances.
+/// Returns `None` when the derivative can't be evaluated (e.g. a singular mass matrix).
+pub type Derivative<'a> = dyn Fn(f64, &DVector<f64>) -> Option<DVector<f64>> + 'a;
+
+/// The chain's equations of motion as `Universe` hands them to an [`Integrator`], for
+/// integrators that need more than the right-hand side.
+pub trait Dynamics {
+    /// `f(t, y)` in the layout of the integrator's [`StateSpace`].
+    fn derivative(&self, t: f64, state: &DVector<f64>) -> Option<DVector<f64>>;
+
+    /// Mass matrix `M(q)` of the chain at the angles `q`, for integrators built from the
+    /// Lagrangian `L = 1/2 * omega^T * M(q) * omega - V(q)`.
+    fn mass_matrix(&self, thetas: &DVector<f64>) -> DMatrix<f64>;
+
+    /// Jacobian `df/dy` at `(t, state)`, by finite differences unless overridden.
+    fn jacobian(&self, t: f64, state: &DVector<f64>) -> Option<DMatrix<f64>> {
+        finite_difference_jacobian(&|t, y| self.derivative(t, y), t, state)
+    }
+
+    /// `f(t, y)` of the `Velocities` layout evaluated on truncated power series, which
+    /// yields the Taylor coefficients of the derivative along a series solution.
+    fn series_derivative(
+        &self,
+        t: &Series<f64>,
+        state: &[Series<f64>]
+    ) -> Option<Vec<Series<f64>>>;
+
+    /// Called by adaptive integrators after each internal step they accept, from `before` at
+    /// `t` to `after` at `t + dt`, so quantities accumulated along the trajectory can follow
+    /// the steps actually taken. Does nothing by default.
+    fn accepted_step(&self, _t: f64, _before: &DVector<f64>, _after: &DVector<f64>, _dt: f64) {}
+
+    /// Whether coordinate `index` is an angle, rather than a length. All of them are unless
+    /// overridden.
+    fn is_angle(&self, _index: usize) -> bool {
+        true
+    }
+}
+
+// What an adaptive integrator reports each accepted internal step to, see
+// `Dynamics::accepted_step`
+type AcceptedStep<'a> = dyn Fn(f64, &DVector<f64>, &DVector<f64>, f64) + 'a;
+
+/// Layout of the state vector handed to an [`Integrator`] for a chain of `n` balls.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StateSpace {
+    /// `[thetas; omegas]`, the derivative is `[omegas; theta_ddots]`
+    Velocities,
+    /// `[thetas; momenta]` with `p = M * omega`, what symplectic methods need
+    Momenta,
+}
+
+/// What an integrator did during its last steps, for the getters on `Universe`.
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct StepStatistics {
+    pub accepted_steps: u32,
+    pub rejected_steps: u32,
+    pub last_step_size: f64,
+    pub iterations: u32,
+    // Local error of the last accepted step, relative to the tolerances
+    pub error_estimate: f64,
+}
+
+/// A time-stepping scheme. Implement this to plug a custom integrator into a `Universe`
+/// with `Universe::set_integrator`.
+pub trait Integrator: IntegratorClone {
+    /// Advances `state` from time `t` by `dt` (which may be negative), or returns `None`
+    /// if the step failed.
+    fn step(
+        &mut self,
+        f: &Derivative,
+        t: f64,
+        state: &DVector<f64>,
+        dt: f64
+    ) -> Option<DVector<f64>>;
+
+    /// Like `step`, for integrators that need more of the equations of motion than `f`.
+    /// `Universe` always steps through this; by default it calls `step` with
+    /// `dynamics.derivative`.
+    fn step_dynamics(
+        &mut self,
+        dynamics: &dyn Dynamics,
+        t: f64,
+        state: &DVector<f64>,
+        dt: f64
+    ) -> Option<DVector<f64>> {
+        self.step(&|t, y| dynamics.derivative(t, y), t, state, dt)
+    }
+
+    /// Which state layout `step` expects.
+    fn state_space(&self) -> StateSpace {
+        StateSpace::Velocities
+    }
+
+    /// Adaptive integrators get the whole frame in one `step` call and choose their own
+    /// step sizes, instead of the fixed substeps `Universe::time_step` takes otherwise.
+    fn is_adaptive(&self) -> bool {
+        false
+    }
+
+    /// Whether a step of `-dt` undoes a step of `dt`, so the simulation can be run
+    /// backwards with `Universe::set_time_reversed`.
+    fn is_time_symmetric(&self) -> bool {
+        false
+    }
+
+    /// Forgets anything carried over from previous steps. Called whenever the state is
+    /// edited from outside.
+    fn reset(&mut self) {}
+
+    fn statistics(&self) -> StepStatistics {
+        StepStatistics::default()
+    }
+
+    fn reset_statistics(&mut self) {}
+}
+
+// Lets `Box<dyn Integrator>` be cloned along with the `Universe` holding it
+pub trait IntegratorClone {
+    fn clone_box(&self) -> Box<dyn Integrator>;
+}
+
+impl<T: Integrator + Clone + 'static> IntegratorClone for T {
+    fn clone_box(&self) -> Box<dyn Integrator> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Integrator> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// Jacobian of `f` at `(t, state)` from forward differences, one evaluation per column.
+pub fn finite_difference_jacobian(
+    f: &Derivative,
+    t: f64,
+    state: &DVector<f64>
+) -> Option<DMatrix<f64>> {
+    let f0 = f(t, state)?;
+    let mut jacobian = DMatrix::from_element(f0.len(), state.len(), 0.0);
+    for j in 0..state.len() {
+        let h = f64::EPSILON.sqrt() * (1.0 + state[j].abs());
+        let mut shifted = state.clone();
+        shifted[j] += h;
+        jacobian.set_column(j, &((f(t, &shifted)? - &f0) / h));
+    }
+    Some(jacobian)
+}
+
+// Splits a [positions; velocities] state into its halves
+fn halves(state: &DVector<f64>) -> (DVector<f64>, DVector<f64>) {
+    let n = state.len() / 2;
+    (state.rows(0, n).into_owned(), state.rows(n, n).into_owned())
+}
+
+fn join(positions: &DVector<f64>, velocities: &DVector<f64>) -> DVector<f64> {
+    let n = positions.len();
+    let mut state = DVector::from_element(2 * n, 0.0);
+    state.rows_mut(0, n).copy_from(positions);
+    state.rows_mut(n, n).copy_from(velocities);
+    state
+}
+
+// Root mean square of the error scaled by abs_tolerance + rel_tolerance * |y|, so a step
+// with an error below 1 meets the tolerances. Non-finite errors count as infinitely large.
+fn scaled_error(
+    error: &DVector<f64>,
+    before: &DVector<f64>,
+    after: &DVector<f64>,
+    abs_tolerance: f64,
+    rel_tolerance: f64
+) -> f64 {
+    let mut sum = 0.0;
+    for i in 0..error.len() {
+        let scale = abs_tolerance + rel_tolerance * f64::max(before[i].abs(), after[i].abs());
+        sum += f64::powi(error[i] / scale, 2);
+    }
+    let error = f64::sqrt(sum / (error.len().max(1) as f64));
+    if error.is_finite() { error } else { f64::INFINITY }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Euler;
+
+impl Integrator for Euler {
+    // Semi-implicit: the new velocity is used to move the positions
+    fn step(
+        &mut self,
+        f: &Derivative,
+        t: f64,
+        state: &DVector<f64>,
+        dt: f64
+    ) -> Option<DVector<f64>> {
+        let (thetas, theta_dots) = halves(state);
+        let (_, theta_ddots) = halves(&f(t, state)?);
+
+        let new_theta_dots = theta_dots + theta_ddots * dt;
+        let new_thetas = thetas + &new_theta_dots * dt;
+        Some(join(&new_thetas, &new_theta_dots))
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct RungeKutta4;
+
+impl Integrator for RungeKutta4 {
+    fn step(
+        &mut self,
+        f: &Derivative,
+        t: f64,
+        state: &DVector<f64>,
+        dt: f64
+    ) -> Option<DVector<f64>> {
+        let k1 = f(t, state)?;
+        let k2 = f(t + 0.5 * dt, &(state + &k1 * (0.5 * dt)))?;
+        let k3 = f(t + 0.5 * dt, &(state + &k2 * (0.5 * dt)))?;
+        let k4 = f(t + dt, &(state + &k3 * dt))?;
+
+        // (k1 + 2*k2 + 2*k3 + k4) * dt/6
+        Some(state + (k1 + k2 * 2.0 + k3 * 2.0 + k4) * (dt / 6.0))
+    }
+}
+
+// Closing half kick v(t+dt) = v_half + a(t+dt)*dt/2 of SymmetricLeapfrog, with the
+// acceleration taken at the new velocities. Solved by fixed-point iteration, which contracts
+// by about dt/2 times the acceleration's dependence on the velocities.
+fn implicit_half_kick(
+    f: &Derivative,
+    t: f64,
+    positions: &DVector<f64>,
+    half: &DVector<f64>,
+    dt: f64
+) -> Option<DVector<f64>> {
+    const TOLERANCE: f64 = 1e-15;
+    const MAX_ITERATIONS: u32 = 50;
+
+    let mut velocities = half.clone();
+    for _ in 0..MAX_ITERATIONS {
+        let (_, theta_ddots) = halves(&f(t, &join(positions, &velocities))?);
+        let next = half + theta_ddots * (dt / 2.0);
+        let change = (&next - &velocities).amax();
+        let size = next.amax();
+        velocities = next;
+        if !change.is_finite() {
+            return None;
+        }
+        if change <= TOLERANCE * (1.0 + size) {
+            break;
+        }
+    }
+    Some(velocities)
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Verlet;
+
+impl Integrator for Verlet {
+    // Based on: x(t+dt) = x(t) + v(t)*dt + 0.5*a(t)*dt^2
+    // Then: v(t+dt) = v(t) + 0.5*(a(t) + a(t+dt))*dt
+    fn step(
+        &mut self,
+        f: &Derivative,
+        t: f64,
+        state: &DVector<f64>,
+        dt: f64
+    ) -> Option<DVector<f64>> {
+        let (thetas, theta_dots) = halves(state);
+        let (_, theta_ddots) = halves(&f(t, state)?);
+
+        let new_thetas = &thetas + &theta_dots * dt + &theta_ddots * (0.5 * dt * dt);
+
+        // Accelerations at the new positions (with the old velocities)
+        let (_, theta_ddots_new) = halves(&f(t + dt, &join(&new_thetas, &theta_dots))?);
+
+        let new_theta_dots = theta_dots + (theta_ddots + theta_ddots_new) * (0.5 * dt);
+        Some(join(&new_thetas, &new_theta_dots))
+    }
+
+    // Only symmetric if the accelerations don't depend on the velocities. The chain's do, so
+    // a forward-backward round trip misses the start by the step's error (see
+    // SymmetricLeapfrog).
+    fn is_time_symmetric(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Leapfrog;
+
+impl Integrator for Leapfrog {
+    // Based on: v(t+dt/2) = v(t) + a(t)*dt/2
+    //           x(t+dt) = x(t) + v(t+dt/2)*dt
+    //           a(t+dt) = acceleration at new position
+    //           v(t+dt) = v(t+dt/2) + a(t+dt)*dt/2
+    fn step(
+        &mut self,
+        f: &Derivative,
+        t: f64,
+        state: &DVector<f64>,
+        dt: f64
+    ) -> Option<DVector<f64>> {
+        let (thetas, theta_dots) = halves(state);
+        let (_, theta_ddots) = halves(&f(t, state)?);
+
+        let theta_dots_half = theta_dots + theta_ddots * (dt / 2.0);
+        let new_thetas = thetas + &theta_dots_half * dt;
+
+        let (_, theta_ddots_new) = halves(&f(t + dt, &join(&new_thetas, &theta_dots_half))?);
+
+        let new_theta_dots = theta_dots_half + theta_ddots_new * (dt / 2.0);
+        Some(join(&new_thetas, &new_theta_dots))
+    }
+
+    // Not symmetric for the chain's velocity-dependent accelerations, see Verlet
+    fn is_time_symmetric(&self) -> bool {
+        false
+    }
+}
+
+// Leapfrog with the closing half kick taken at the new velocities, so a step of -dt starts
+// with the same kick and retraces the step even though the chain's accelerations depend on
+// the velocities. Costs a few extra evaluations per step for the fixed-point iteration.
+#[derive(Clone, Copy, Default)]
+pub struct SymmetricLeapfrog;
+
+impl Integrator for SymmetricLeapfrog {
+    // Based on: v(t+dt/2) = v(t) + a(t, v(t))*dt/2
+    //           x(t+dt) = x(t) + v(t+dt/2)*dt
+    //           v(t+dt) = v(t+dt/2) + a(t+dt, v(t+dt))*dt/2
+    fn step(
+        &mut self,
+        f: &Derivative,
+        t: f64,
+        state: &DVector<f64>,
+        dt: f64
+    ) -> Option<DVector<f64>> {
+        let (thetas, theta_dots) = halves(state);
+        let (_, theta_ddots) = halves(&f(t, state)?);
+
+        let theta_dots_half = theta_dots + theta_ddots * (dt / 2.0);
+        let new_thetas = thetas + &theta_dots_half * dt;
+
+        let new_theta_dots = implicit_half_kick(f, t + dt, &new_thetas, &theta_dots_half, dt)?;
+        Some(join(&new_thetas, &new_theta_dots))
+    }
+
+    fn is_time_symmetric(&self) -> bool {
+        true
+    }
+}
+
+// Dormand–Prince RK5(4). Each attempt compares the 5th and 4th order solutions; the step
+// is accepted when the scaled error is below 1 and the next step size is grown or shrunk
+// from that error.
+#[derive(Clone)]
+pub struct DormandPrince {
+    pub abs_tolerance: f64,
+    pub rel_tolerance: f64,
+    proposed_step_size: f64,
+    statistics: StepStatistics,
+}
+
+impl DormandPrince {
+    pub fn new(abs_tolerance: f64, rel_tolerance: f64) -> Self {
+        Self {
+            abs_tolerance,
+            rel_tolerance,
+            proposed_step_size: 0.0, // Chosen on the first step
+            statistics: StepStatistics::default(),
+        }
+    }
+
+    // The steps over the interval, reporting each accepted one
+    fn integrate(
+        &mut self,
+        f: &Derivative,
+        report: &AcceptedStep,
+        t: f64,
+        state: &DVector<f64>,
+        interval: f64
+    ) -> Option<DVector<f64>> {
+        const C: [f64; 7] = [0.0, 1.0 / 5.0, 3.0 / 10.0, 4.0 / 5.0, 8.0 / 9.0, 1.0, 1.0];
+        const A: [[f64; 6]; 7] = [
+            [0.0; 6],
+            [1.0 / 5.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            [3.0 / 40.0, 9.0 / 40.0, 0.0, 0.0, 0.0, 0.0],
+            [44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0, 0.0, 0.0, 0.0],
+            [19372.0 / 6561.0, -25360.0 / 2187.0, 64448.0 / 6561.0, -212.0 / 729.0, 0.0, 0.0],
+            [9017.0 / 3168.0, -355.0 / 33.0, 46732.0 / 5247.0, 49.0 / 176.0, -5103.0 / 18656.0, 0.0],
+            [35.0 / 384.0, 0.0, 500.0 / 1113.0, 125.0 / 192.0, -2187.0 / 6784.0, 11.0 / 84.0],
+        ];
+        // Difference between the 5th order weights (last row of A) and the 4th order ones
+        const E: [f64; 7] = [
+            71.0 / 57600.0,
+            0.0,
+            -71.0 / 16695.0,
+            71.0 / 1920.0,
+            -17253.0 / 339200.0,
+            22.0 / 525.0,
+            -1.0 / 40.0,
+        ];
+        const MAX_ATTEMPTS: u32 = 100_000;
+
+        if interval == 0.0 {
+            return Some(state.clone());
+        }
+
+        let mut state = state.clone();
+        let direction = interval.signum();
+        let mut h = if self.proposed_step_size > 0.0 {
+            self.proposed_step_size.min(interval.abs())
+        } else {
+            interval.abs() / 50.0
+        };
+        let mut elapsed = 0.0;
+        let mut k1 = f(t, &state)?;
+        let mut attempts = 0;
+
+        while elapsed < interval.abs() {
+            attempts += 1;
+            if attempts > MAX_ATTEMPTS || h < interval.abs() * 1e-12 {
+                return None; // Step size collapsed, the solution is most likely blowing up
+            }
+
+            let step = h.min(interval.abs() - elapsed);
+            let signed_step = direction * step;
+            let stage_time = t + direction * elapsed;
+
+            let mut k = vec![k1.clone()];
+            for (row, c) in A.iter().zip(C).skip(1) {
+                let mut y = state.clone();
+                for (j, kj) in k.iter().enumerate() {
+                    if row[j] != 0.0 {
+                        y += kj * (row[j] * signed_step);
+                    }
+                }
+                k.push(f(stage_time + c * signed_step, &y)?);
+            }
+
+            // The 7th stage is evaluated at the 5th order solution (first same as last)
+            let mut new_state = state.clone();
+            for (j, kj) in k.iter().take(6).enumerate() {
+                new_state += kj * (A[6][j] * signed_step);
+            }
+
+            let mut local_error = DVector::from_element(state.len(), 0.0);
+            for (e, kj) in E.iter().zip(&k) {
+                local_error += kj * (e * signed_step);
+            }
+            let error = scaled_error(
+                &local_error,
+                &state,
+                &new_state,
+                self.abs_tolerance,
+                self.rel_tolerance
+            );
+
+            if error <= 1.0 {
+                report(stage_time, &state, &new_state, signed_step);
+                state = new_state;
+                k1 = k.swap_remove(6);
+                elapsed += step;
+                self.statistics.accepted_steps += 1;
+                self.statistics.last_step_size = signed_step;
+                self.statistics.error_estimate = error;
+
+                let factor = if error == 0.0 {
+                    5.0
+                } else {
+                    (0.9 * f64::powf(error, -0.2)).clamp(0.2, 5.0)
+                };
+                // Don't let a short final step (clipped to the interval) shrink the next one
+                h = f64::max(h, step) * factor;
+            } else {
+                self.statistics.rejected_steps += 1;
+                let factor = if error.is_finite() {
+                    (0.9 * f64::powf(error, -0.2)).clamp(0.2, 1.0)
+                } else {
+                    0.2
+                };
+                h = step * factor;
+            }
+        }
+        self.proposed_step_size = h;
+
+        Some(state)
+    }
+}
+
+impl Integrator for DormandPrince {
+    fn step(
+        &mut self,
+        f: &Derivative,
+        t: f64,
+        state: &DVector<f64>,
+        interval: f64
+    ) -> Option<DVector<f64>> {
+        self.integrate(f, &|_, _, _, _| {}, t, state, interval)
+    }
+
+    fn step_dynamics(
+        &mut self,
+        dynamics: &dyn Dynamics,
+        t: f64,
+        state: &DVector<f64>,
+        interval: f64
+    ) -> Option<DVector<f64>> {
+        self.integrate(
+            &|t, y| dynamics.derivative(t, y),
+            &|t, before, after, dt| dynamics.accepted_step(t, before, after, dt),
+            t,
+            state,
+            interval
+        )
+    }
+
+    fn is_adaptive(&self) -> bool {
+        true
+    }
+
+    fn reset(&mut self) {
+        self.proposed_step_size = 0.0;
+    }
+
+    fn statistics(&self) -> StepStatistics {
+        self.statistics
+    }
+
+    fn reset_statistics(&mut self) {
+        self.statistics.accepted_steps = 0;
+        self.statistics.rejected_steps = 0;
+    }
+}
+
+// Gragg–Bulirsch–Stoer: modified midpoint steps with 2, 4, 6, ... substeps are
+// extrapolated to zero substep size (the error expansion only has even powers). The
+// difference between the last two extrapolation orders is the error estimate; if it
+// doesn't meet the tolerances by `max_order` the step is halved and retried.
+#[derive(Clone)]
+pub struct BulirschStoer {
+    pub abs_tolerance: f64,
+    pub rel_tolerance: f64,
+    pub max_order: usize,
+    proposed_step_size: f64,
+    statistics: StepStatistics,
+}
+
+impl BulirschStoer {
+    pub fn new(abs_tolerance: f64, rel_tolerance: f64, max_order: usize) -> Self {
+        Self {
+            abs_tolerance,
+            rel_tolerance,
+            max_order: max_order.max(2),
+            proposed_step_size: 0.0, // Chosen on the first step
+            statistics: StepStatistics::default(),
+        }
+    }
+
+    // Gragg's modified midpoint method over `h` with `substeps` substeps
+    fn modified_midpoint(
+        f: &Derivative,
+        t: f64,
+        state: &DVector<f64>,
+        derivative: &DVector<f64>,
+        h: f64,
+        substeps: usize
+    ) -> Option<DVector<f64>> {
+        let sub_h = h / (substeps as f64);
+        let mut previous = state.clone();
+        let mut current = state + derivative * sub_h;
+        for m in 1..substeps {
+            let next = &previous + f(t + (m as f64) * sub_h, &current)? * (2.0 * sub_h);
+            previous = current;
+            current = next;
+        }
+        let end = f(t + h, &current)?;
+        Some((previous + &current + end * sub_h) * 0.5)
+    }
+
+    // The steps over the interval, reporting each accepted one
+    fn integrate(
+        &mut self,
+        f: &Derivative,
+        report: &AcceptedStep,
+        t: f64,
+        state: &DVector<f64>,
+        interval: f64
+    ) -> Option<DVector<f64>> {
+        const MAX_ATTEMPTS: u32 = 100_000;
+
+        if interval == 0.0 {
+            return Some(state.clone());
+        }
+
+        let mut state = state.clone();
+        let direction = interval.signum();
+        let mut h = if self.proposed_step_size > 0.0 {
+            self.proposed_step_size.min(interval.abs())
+        } else {
+            interval.abs() / 10.0
+        };
+        let mut elapsed = 0.0;
+        let mut attempts = 0;
+
+        while elapsed < interval.abs() {
+            attempts += 1;
+            if attempts > MAX_ATTEMPTS || h < interval.abs() * 1e-12 {
+                return None; // Step size collapsed, the solution is most likely blowing up
+            }
+
+            let step = h.min(interval.abs() - elapsed);
+            let signed_step = direction * step;
+            let step_time = t + direction * elapsed;
+            let derivative = f(step_time, &state)?;
+
+            // table[k][j] is the j-th extrapolation from the k-th substep count
+            let mut table: Vec<Vec<DVector<f64>>> = Vec::with_capacity(self.max_order);
+            let mut accepted = None;
+            let mut error = f64::INFINITY;
+            for k in 0..self.max_order {
+                let substeps = 2 * (k + 1);
+                let mut row = vec![
+                    Self::modified_midpoint(f, step_time, &state, &derivative, signed_step, substeps)?
+                ];
+                for j in 1..=k {
+                    // Neville extrapolation in h^2 towards h = 0
+                    let ratio = (substeps as f64) / ((2 * (k + 1 - j)) as f64);
+                    let next = &row[j - 1] + (&row[j - 1] - &table[k - 1][j - 1]) / (ratio * ratio - 1.0);
+                    row.push(next);
+                }
+
+                if k > 0 {
+                    error = scaled_error(
+                        &(&row[k] - &row[k - 1]),
+                        &state,
+                        &row[k],
+                        self.abs_tolerance,
+                        self.rel_tolerance
+                    );
+                    if error <= 1.0 {
+                        accepted = Some((row[k].clone(), k));
+                        break;
+                    }
+                }
+                table.push(row);
+            }
+
+            match accepted {
+                Some((new_state, k)) => {
+                    report(step_time, &state, &new_state, signed_step);
+                    state = new_state;
+                    elapsed += step;
+                    self.statistics.accepted_steps += 1;
+                    self.statistics.last_step_size = signed_step;
+                    self.statistics.iterations = k as u32 + 1;
+                    self.statistics.error_estimate = error;
+
+                    // The extrapolated solution of column k has order 2k + 2
+                    let factor = if error == 0.0 {
+                        4.0
+                    } else {
+                        (0.94 * f64::powf(0.65 / error, 1.0 / ((2 * k + 1) as f64))).clamp(0.2, 4.0)
+                    };
+                    h = f64::max(h, step) * factor;
+                }
+                None => {
+                    self.statistics.rejected_steps += 1;
+                    h = step * 0.5;
+                }
+            }
+        }
+        self.proposed_step_size = h;
+
+        Some(state)
+    }
+}
+
+impl Integrator for BulirschStoer {
+    fn step(
+        &mut self,
+        f: &Derivative,
+        t: f64,
+        state: &DVector<f64>,
+        interval: f64
+    ) -> Option<DVector<f64>> {
+        self.integrate(f, &|_, _, _, _| {}, t, state, interval)
+    }
+
+    fn step_dynamics(
+        &mut self,
+        dynamics: &dyn Dynamics,
+        t: f64,
+        state: &DVector<f64>,
+        interval: f64
+    ) -> Option<DVector<f64>> {
+        self.integrate(
+            &|t, y| dynamics.derivative(t, y),
+            &|t, before, after, dt| dynamics.accepted_step(t, before, after, dt),
+            t,
+            state,
+            interval
+        )
+    }
+
+    fn is_adaptive(&self) -> bool {
+        true
+    }
+
+    fn reset(&mut self) {
+        self.proposed_step_size = 0.0;
+    }
+
+    fn statistics(&self) -> StepStatistics {
+        self.statistics
+    }
+
+    fn reset_statistics(&mut self) {
+        self.statistics.accepted_steps = 0;
+        self.statistics.rejected_steps = 0;
+    }
+}
+
+// Implicit Gauss–Legendre Runge–Kutta on the canonical state. The stage equations
+// K_i = f(y + dt * sum_j a_ij K_j) are solved by fixed-point iteration. Gauss–Legendre
+// methods are symplectic, and because they work on (theta, p) rather than
+// (theta, omega) the energy error stays bounded instead of drifting. A step whose iteration
+// doesn't converge is retried in two halves.
+#[derive(Clone)]
+pub struct GaussLegendre {
+    a: Vec<Vec<f64>>,
+    b: Vec<f64>,
+    c: Vec<f64>,
+    pub tolerance: f64,
+    pub max_iterations: u32,
+    statistics: StepStatistics,
+}
+
+impl GaussLegendre {
+    // 2 stages, order 4
+    pub fn two_stage(tolerance: f64, max_iterations: u32) -> Self {
+        let sqrt3 = f64::sqrt(3.0);
+        Self {
+            a: vec![
+                vec![1.0 / 4.0, 1.0 / 4.0 - sqrt3 / 6.0],
+                vec![1.0 / 4.0 + sqrt3 / 6.0, 1.0 / 4.0]
+            ],
+            b: vec![1.0 / 2.0, 1.0 / 2.0],
+            c: vec![1.0 / 2.0 - sqrt3 / 6.0, 1.0 / 2.0 + sqrt3 / 6.0],
+            tolerance,
+            max_iterations,
+            statistics: StepStatistics::default(),
+        }
+    }
+
+    // 3 stages, order 6
+    pub fn three_stage(tolerance: f64, max_iterations: u32) -> Self {
+        let sqrt15 = f64::sqrt(15.0);
+        Self {
+            a: vec![
+                vec![5.0 / 36.0, 2.0 / 9.0 - sqrt15 / 15.0, 5.0 / 36.0 - sqrt15 / 30.0],
+                vec![5.0 / 36.0 + sqrt15 / 24.0, 2.0 / 9.0, 5.0 / 36.0 - sqrt15 / 24.0],
+                vec![5.0 / 36.0 + sqrt15 / 30.0, 2.0 / 9.0 + sqrt15 / 15.0, 5.0 / 36.0]
+            ],
+            b: vec![5.0 / 18.0, 4.0 / 9.0, 5.0 / 18.0],
+            c: vec![1.0 / 2.0 - sqrt15 / 10.0, 1.0 / 2.0, 1.0 / 2.0 + sqrt15 / 10.0],
+            tolerance,
+            max_iterations,
+            statistics: StepStatistics::default(),
+        }
+    }
+
+    // One step of dt, None if the stage iteration blew up or didn't converge within
+    // max_iterations
+    fn attempt(
+        &mut self,
+        f: &Derivative,
+        t: f64,
+        state: &DVector<f64>,
+        dt: f64
+    ) -> Option<DVector<f64>> {
+        let initial = f(t, state)?;
+        let mut k = vec![initial; self.b.len()];
+
+        let mut converged = false;
+        for _ in 0..self.max_iterations.max(1) {
+            self.statistics.iterations += 1;
+            let mut next = Vec::with_capacity(k.len());
+            for (row, c) in self.a.iter().zip(&self.c) {
+                let mut y = state.clone();
+                for (aij, kj) in row.iter().zip(&k) {
+                    y += kj * (aij * dt);
+                }
+                next.push(f(t + c * dt, &y)?);
+            }
+
+            let mut change: f64 = 0.0;
+            let mut size: f64 = 0.0;
+            for (old, new) in k.iter().zip(&next) {
+                change = change.max((new - old).amax());
+                size = size.max(new.amax());
+            }
+            k = next;
+            if !change.is_finite() {
+                return None;
+            }
+            if change <= self.tolerance * (1.0 + size) {
+                converged = true;
+                break;
+            }
+        }
+        if !converged {
+            return None;
+        }
+
+        let mut new_state = state.clone();
+        for (bi, ki) in self.b.iter().zip(&k) {
+            new_state += ki * (bi * dt);
+        }
+        Some(new_state)
+    }
+
+    // Step by dt, splitting it in halves (recursively) where the stage iteration fails.
+    // Every failed attempt counts as a rejected step.
+    fn subdivided_step(
+        &mut self,
+        f: &Derivative,
+        t: f64,
+        state: &DVector<f64>,
+        dt: f64,
+        halvings: u32
+    ) -> Option<DVector<f64>> {
+        const MAX_HALVINGS: u32 = 6;
+
+        if let Some(new_state) = self.attempt(f, t, state, dt) {
+            return Some(new_state);
+        }
+        self.statistics.rejected_steps += 1;
+        if halvings == MAX_HALVINGS {
+            return None;
+        }
+        let middle = self.subdivided_step(f, t, state, 0.5 * dt, halvings + 1)?;
+        self.subdivided_step(f, t + 0.5 * dt, &middle, 0.5 * dt, halvings + 1)
+    }
+}
+
+impl Integrator for GaussLegendre {
+    fn step(
+        &mut self,
+        f: &Derivative,
+        t: f64,
+        state: &DVector<f64>,
+        dt: f64
+    ) -> Option<DVector<f64>> {
+        self.statistics.iterations = 0;
+        let new_state = self.subdivided_step(f, t, state, dt, 0)?;
+        self.statistics.accepted_steps += 1;
+        self.statistics.last_step_size = dt;
+        Some(new_state)
+    }
+
+    fn state_space(&self) -> StateSpace {
+        StateSpace::Momenta
+    }
+
+    fn is_time_symmetric(&self) -> bool {
+        true
+    }
+
+    fn statistics(&self) -> StepStatistics {
+        self.statistics
+    }
+
+    fn reset_statistics(&mut self) {
+        self.statistics.accepted_steps = 0;
+        self.statistics.rejected_steps = 0;
+    }
+}
+
+// Adams–Bashforth–Moulton predictor–corrector of order 4 in PEC mode: the AB4 predictor
+// is evaluated once and the AM4 corrector reuses that evaluation, so a step costs a single
+// derivative evaluation. The evaluations are kept as history for the next steps; until four
+// of them exist the method bootstraps itself with RK4 steps.
+#[derive(Clone, Default)]
+pub struct AdamsBashforthMoulton {
+    // Derivatives at the current step and the three before it, most recent first
+    history: VecDeque<DVector<f64>>,
+    history_step_size: f64,
+    statistics: StepStatistics,
+}
+
+impl AdamsBashforthMoulton {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Re-sample the history at a new spacing using the cubic through the stored values.
+    // Returns false if the step grew, which would extrapolate the cubic past the oldest
+    // value, or shrank too much for the re-sampling to be accurate.
+    fn rescale_history(&mut self, dt: f64) -> bool {
+        let ratio = dt / self.history_step_size;
+        if (ratio - 1.0).abs() < 1e-12 {
+            return true;
+        }
+        if !(0.5..1.0).contains(&ratio) {
+            return false;
+        }
+
+        // Stored values sit at s = 0, -1, -2, -3 (in units of the old step)
+        let nodes = [0.0, -1.0, -2.0, -3.0];
+        let mut rescaled = VecDeque::with_capacity(4);
+        for m in 0..4 {
+            let s = -(m as f64) * ratio;
+            let mut value = DVector::from_element(self.history[0].len(), 0.0);
+            for (i, fi) in self.history.iter().enumerate() {
+                let mut weight = 1.0;
+                for (j, node) in nodes.iter().enumerate() {
+                    if i != j {
+                        weight *= (s - node) / (nodes[i] - node);
+                    }
+                }
+                value += fi * weight;
+            }
+            rescaled.push_back(value);
+        }
+        self.history = rescaled;
+        self.history_step_size = dt;
+        true
+    }
+}
+
+impl Integrator for AdamsBashforthMoulton {
+    fn step(
+        &mut self,
+        f: &Derivative,
+        t: f64,
+        state: &DVector<f64>,
+        dt: f64
+    ) -> Option<DVector<f64>> {
+        let consistent = self.history
+            .front()
+            .is_some_and(|front| front.len() == state.len());
+        if !consistent {
+            self.history.clear();
+            self.history.push_front(f(t, state)?);
+            self.history_step_size = dt;
+        }
+        if self.history.len() == 4 && !self.rescale_history(dt) {
+            let current = self.history.pop_front()?;
+            self.history.clear();
+            self.history.push_front(current);
+            self.history_step_size = dt;
+        }
+        if self.history.len() < 4 {
+            // Bootstrap with RK4, reusing the stored derivative as its first stage
+            let k1 = self.history[0].clone();
+            let k2 = f(t + 0.5 * dt, &(state + &k1 * (0.5 * dt)))?;
+            let k3 = f(t + 0.5 * dt, &(state + &k2 * (0.5 * dt)))?;
+            let k4 = f(t + dt, &(state + &k3 * dt))?;
+            let new_state = state + (k1 + k2 * 2.0 + k3 * 2.0 + k4) * (dt / 6.0);
+
+            self.history.push_front(f(t + dt, &new_state)?);
+            self.history_step_size = dt;
+            self.statistics.last_step_size = dt;
+            return Some(new_state);
+        }
+
+        let (f0, f1, f2, f3) = (&self.history[0], &self.history[1], &self.history[2], &self.history[3]);
+        let predicted = state + (f0 * 55.0 - f1 * 59.0 + f2 * 37.0 - f3 * 9.0) * (dt / 24.0);
+        let f_predicted = f(t + dt, &predicted)?;
+        let corrected = state + (&f_predicted * 9.0 + f0 * 19.0 - f1 * 5.0 + f2) * (dt / 24.0);
+
+        self.history.pop_back();
+        self.history.push_front(f_predicted);
+        self.statistics.accepted_steps += 1;
+        self.statistics.last_step_size = dt;
+        Some(corrected)
+    }
+
+    fn reset(&mut self) {
+        self.history.clear();
+    }
+
+    fn statistics(&self) -> StepStatistics {
+        self.statistics
+    }
+
+    fn reset_statistics(&mut self) {
+        self.statistics.accepted_steps = 0;
+        self.statistics.rejected_steps = 0;
+    }
+}
+
+// Variational integrator from the trapezoidal discrete Lagrangian
+//   L_d(q0, q1) = dt/2 * (L(q0, v) + L(q1, v)),  v = (q1 - q0) / dt
+// In position–momentum form the discrete Euler–Lagrange equations read
+//   p0 = (M(q0) + M(q1)) / 2 * v - dt/2 * dL/dq(q0, v)
+//   p1 = (M(q0) + M(q1)) / 2 * v + dt/2 * dL/dq(q1, v)
+// The first is solved for v by fixed-point iteration, the second is explicit. The map is
+// symplectic and conserves the discrete momentum maps of any symmetry of L_d (the total
+// angular momentum when gravity is off) by construction. With a constant mass matrix it
+// reduces to Störmer–Verlet.
+#[derive(Clone)]
+pub struct Variational {
+    pub tolerance: f64,
+    pub max_iterations: u32,
+    statistics: StepStatistics,
+}
+
+impl Variational {
+    pub fn new(tolerance: f64, max_iterations: u32) -> Self {
+        Self { tolerance, max_iterations, statistics: StepStatistics::default() }
+    }
+
+    // dL/dq at the angles q moving with velocity v, read off the canonical derivative
+    fn lagrangian_gradient(
+        dynamics: &dyn Dynamics,
+        mass_matrix: &DMatrix<f64>,
+        t: f64,
+        q: &DVector<f64>,
+        v: &DVector<f64>
+    ) -> Option<DVector<f64>> {
+        let (_, gradient) = halves(&dynamics.derivative(t, &join(q, &(mass_matrix * v)))?);
+        Some(gradient)
+    }
+}
+
+impl Integrator for Variational {
+    // The discrete Lagrangian can't be evaluated without the mass matrix, see `step_dynamics`
+    fn step(
+        &mut self,
+        _f: &Derivative,
+        _t: f64,
+        _state: &DVector<f64>,
+        _dt: f64
+    ) -> Option<DVector<f64>> {
+        None
+    }
+
+    fn step_dynamics(
+        &mut self,
+        dynamics: &dyn Dynamics,
+        t: f64,
+        state: &DVector<f64>,
+        dt: f64
+    ) -> Option<DVector<f64>> {
+        let (q0, p0) = halves(state);
+        let m0 = dynamics.mass_matrix(&q0);
+        let mut v = LU::new(m0.clone()).solve(&p0)?;
+
+        self.statistics.iterations = 0;
+        for _ in 0..self.max_iterations.max(1) {
+            self.statistics.iterations += 1;
+            let m1 = dynamics.mass_matrix(&(&q0 + &v * dt));
+            let average = (&m0 + m1) * 0.5;
+            let rhs = &p0 + Self::lagrangian_gradient(dynamics, &m0, t, &q0, &v)? * (0.5 * dt);
+            let next = LU::new(average).solve(&rhs)?;
+
+            let change = (&next - &v).amax();
+            let size = next.amax();
+            v = next;
+            if !change.is_finite() {
+                return None;
+            }
+            if change <= self.tolerance * (1.0 + size) {
+                break;
+            }
+        }
+
+        let q1 = &q0 + &v * dt;
+        let m1 = dynamics.mass_matrix(&q1);
+        let p1 =
+            (&m0 + &m1) * &v * 0.5 +
+            Self::lagrangian_gradient(dynamics, &m1, t + dt, &q1, &v)? * (0.5 * dt);
+
+        self.statistics.accepted_steps += 1;
+        self.statistics.last_step_size = dt;
+        Some(join(&q1, &p1))
+    }
+
+    fn state_space(&self) -> StateSpace {
+        StateSpace::Momenta
+    }
+
+    fn is_time_symmetric(&self) -> bool {
+        true
+    }
+
+    fn statistics(&self) -> StepStatistics {
+        self.statistics
+    }
+
+    fn reset_statistics(&mut self) {
+        self.statistics.accepted_steps = 0;
+        self.statistics.rejected_steps = 0;
+    }
+}
+
+// Taylor series method: the Taylor coefficients of the solution around the current state are
+// generated up to `order` by automatic differentiation (the equations of motion evaluated on
+// truncated power series, one more coefficient per pass), then summed with Horner's scheme.
+// The step size comes from the decay of the last two coefficients (Jorba & Zou), so at high
+// orders a frame is usually covered by a handful of steps near machine precision.
+#[derive(Clone)]
+pub struct Taylor {
+    pub order: usize,
+    pub abs_tolerance: f64,
+    pub rel_tolerance: f64,
+    statistics: StepStatistics,
+}
+
+impl Taylor {
+    const REPORTED_PIECES: usize = 4;
+
+    pub fn new(order: usize, abs_tolerance: f64, rel_tolerance: f64) -> Self {
+        Self { order: order.max(2), abs_tolerance, rel_tolerance, statistics: StepStatistics::default() }
+    }
+}
+
+impl Integrator for Taylor {
+    // The coefficients can't be generated from float evaluations of f, see `step_dynamics`
+    fn step(
+        &mut self,
+        _f: &Derivative,
+        _t: f64,
+        _state: &DVector<f64>,
+        _dt: f64
+    ) -> Option<DVector<f64>> {
+        None
+    }
+
+    fn step_dynamics(
+        &mut self,
+        dynamics: &dyn Dynamics,
+        t: f64,
+        state: &DVector<f64>,
+        dt: f64
+    ) -> Option<DVector<f64>> {
+        let mut t = t;
+        let mut y = state.clone();
+        let mut remaining = dt;
+
+        while remaining != 0.0 {
+            let series = taylor_coefficients(
+                &|t, y| dynamics.series_derivative(t, y),
+                t,
+                y.as_slice(),
+                self.order
+            )?;
+            let tolerance = self.abs_tolerance + self.rel_tolerance * y.amax();
+
+            let h_max = taylor_step_size(&series, self.order, tolerance);
+            let h = if h_max >= remaining.abs() { remaining } else { h_max.copysign(dt) };
+            if h == 0.0 || !h.is_finite() {
+                return None;
+            }
+
+            // The steps are long, so they are reported in pieces summed from the same series
+            let mut piece_start = y.clone();
+            for piece in 1..=Self::REPORTED_PIECES {
+                let s = h * (piece as f64) / (Self::REPORTED_PIECES as f64);
+                let piece_end = DVector::from_iterator(
+                    y.len(),
+                    series.iter().map(|series| series.evaluate(s))
+                );
+                let piece_h = h / (Self::REPORTED_PIECES as f64);
+                dynamics.accepted_step(t + s - piece_h, &piece_start, &piece_end, piece_h);
+                piece_start = piece_end;
+            }
+            y = piece_start;
+            self.statistics.accepted_steps += 1;
+            self.statistics.last_step_size = h;
+            self.statistics.error_estimate =
+                (largest_coefficient(&series, self.order) * f64::powi(h.abs(), self.order as i32)) /
+                tolerance;
+
+            t += h;
+            remaining = if h == remaining { 0.0 } else { remaining - h };
+        }
+        Some(y)
+    }
+
+    fn is_adaptive(&self) -> bool {
+        true
+    }
+
+    fn statistics(&self) -> StepStatistics {
+        self.statistics
+    }
+
+    fn reset_statistics(&mut self) {
+        self.statistics.accepted_steps = 0;
+        self.statistics.rejected_steps = 0;
+    }
+}
+
+// Backward differentiation formulas of order 1 (backward Euler) and 2, L-stable, so stiff
+// chains (very different lengths or masses) can take steps far beyond the explicit stability
+// limit at the price of some numerical damping. With a step ratio w = h_n / h_{n-1}, BDF2 reads
+//   y_{n+1} = (1 + w)^2 / (1 + 2w) * y_n - w^2 / (1 + 2w) * y_{n-1} + h * (1 + w) / (1 + 2w) * f(y_{n+1})
+// and the implicit equation is solved by Newton's method with the Jacobian of the chain model.
+// BDF2 starts with a backward Euler step whenever there is no usable previous state. The
+// implicit equation has spurious roots far from the solution (the whole chain spinning), so a
+// step whose Newton iteration stalls or that turns a link by more than MAX_TURN is split in two.
+#[derive(Clone)]
+pub struct Bdf {
+    pub order: usize,
+    pub tolerance: f64,
+    pub max_iterations: u32,
+    // State and step size of the step before the current one
+    previous: Option<(DVector<f64>, f64)>,
+    statistics: StepStatistics,
+}
+
+impl Bdf {
+    const MAX_TURN: f64 = 1.0;
+    const MAX_SPLITS: u32 = 12;
+
+    pub fn backward_euler(tolerance: f64, max_iterations: u32) -> Self {
+        Self::new(1, tolerance, max_iterations)
+    }
+
+    pub fn bdf2(tolerance: f64, max_iterations: u32) -> Self {
+        Self::new(2, tolerance, max_iterations)
+    }
+
+    fn new(order: usize, tolerance: f64, max_iterations: u32) -> Self {
+        Self { order, tolerance, max_iterations, previous: None, statistics: StepStatistics::default() }
+    }
+
+    // (weight of y_n, weight of y_{n-1}, weight of h * f(y_{n+1})) for a step of dt
+    fn coefficients(&self, state: &DVector<f64>, dt: f64) -> (f64, f64, f64) {
+        if self.order >= 2 {
+            if let Some((previous, previous_dt)) = &self.previous {
+                let ratio = dt / previous_dt;
+                // Reuse the history through the jitter of frame times, but restart for steps
+                // that turn around or grow by more than that: variable-step BDF2 loses its
+                // zero-stability as the ratio nears 1 + sqrt(2), so only small growth is kept
+                if previous.len() == state.len() && (0.2..=1.2).contains(&ratio) {
+                    let denominator = 1.0 + 2.0 * ratio;
+                    return (
+                        f64::powi(1.0 + ratio, 2) / denominator,
+                        -(ratio * ratio) / denominator,
+                        (1.0 + ratio) / denominator,
+                    );
+                }
+            }
+        }
+        (1.0, 0.0, 1.0)
+    }
+
+    fn solve(
+        &mut self,
+        dynamics: &dyn Dynamics,
+        t: f64,
+        state: &DVector<f64>,
+        dt: f64
+    ) -> Option<DVector<f64>> {
+        let (current_weight, previous_weight, beta) = self.coefficients(state, dt);
+        let mut known = state * current_weight;
+        if previous_weight != 0.0 {
+            if let Some((previous, _)) = &self.previous {
+                known += previous * previous_weight;
+            }
+        }
+
+        // Newton on G(y) = y - known - dt * beta * f(t + dt, y), starting from y_n
+        let identity = DMatrix::identity(state.len(), state.len());
+        let mut y = state.clone();
+        let mut previous_change = f64::INFINITY;
+        self.statistics.iterations = 0;
+        for _ in 0..self.max_iterations.max(1) {
+            self.statistics.iterations += 1;
+            let residual = &y - &known - dynamics.derivative(t + dt, &y)? * (dt * beta);
+            let jacobian = &identity - dynamics.jacobian(t + dt, &y)? * (dt * beta);
+            let delta = LU::new(jacobian).solve(&residual)?;
+            y -= &delta;
+
+            let change = delta.amax();
+            let scale = 1.0 + y.amax();
+            if !change.is_finite() {
+                return None;
+            }
+            if change <= self.tolerance * scale {
+                return Some(y);
+            }
+            if change > 0.9 * previous_change {
+                // Newton stopped contracting: either round-off keeps it from reaching the
+                // tolerance, or it is heading somewhere else
+                return (change <= self.tolerance.sqrt() * scale).then_some(y);
+            }
+            previous_change = change;
+        }
+        None
+    }
+
+    // A step of dt, split into halves as often as needed
+    fn advance(
+        &mut self,
+        dynamics: &dyn Dynamics,
+        t: f64,
+        state: &DVector<f64>,
+        dt: f64,
+        splits: u32
+    ) -> Option<DVector<f64>> {
+        let n = state.len() / 2;
+        if let Some(new_state) = self.solve(dynamics, t, state, dt) {
+            let turn = (0..n)
+                .filter(|&i| dynamics.is_angle(i))
+                .fold(0.0, |max: f64, i| max.max((new_state[i] - state[i]).abs()));
+            if turn <= Self::MAX_TURN {
+                self.previous = Some((state.clone(), dt));
+                return Some(new_state);
+            }
+        }
+        if splits == Self::MAX_SPLITS {
+            return None;
+        }
+        self.statistics.rejected_steps += 1;
+        let half = self.advance(dynamics, t, state, 0.5 * dt, splits + 1)?;
+        self.advance(dynamics, t + 0.5 * dt, &half, 0.5 * dt, splits + 1)
+    }
+}
+
+impl Integrator for Bdf {
+    // Newton needs the Jacobian, see `step_dynamics`
+    fn step(
+        &mut self,
+        _f: &Derivative,
+        _t: f64,
+        _state: &DVector<f64>,
+        _dt: f64
+    ) -> Option<DVector<f64>> {
+        None
+    }
+
+    fn step_dynamics(
+        &mut self,
+        dynamics: &dyn Dynamics,
+        t: f64,
+        state: &DVector<f64>,
+        dt: f64
+    ) -> Option<DVector<f64>> {
+        let new_state = self.advance(dynamics, t, state, dt, 0)?;
+        self.statistics.accepted_steps += 1;
+        self.statistics.last_step_size = dt;
+        Some(new_state)
+    }
+
+    fn reset(&mut self) {
+        self.previous = None;
+    }
+
+    fn statistics(&self) -> StepStatistics {
+        self.statistics
+    }
+
+    fn reset_statistics(&mut self) {
+        self.statistics.accepted_steps = 0;
+        self.statistics.rejected_steps = 0;
+    }
+}
@@ -0,0 +1,153 @@
+use std::ops::{ Add, Div, Mul, Neg, Sub };
+use std::f64::consts::PI;
+use serde::{ Serialize, Deserialize };
+use crate::scalar::Scalar;
+
+/// Closed interval `[lo, hi]`. Every operation rounds outwards by an ulp, so the result
+/// encloses the exact result for all points of the operands.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Interval {
+    pub lo: f64,
+    pub hi: f64,
+}
+
+// Largest error of the platform sin and cos, generously
+const TRIG_ERROR: f64 = 1e-15;
+
+impl Interval {
+    pub fn new(a: f64, b: f64) -> Self {
+        Self { lo: a.min(b), hi: a.max(b) }
+    }
+
+    pub fn point(value: f64) -> Self {
+        Self { lo: value, hi: value }
+    }
+
+    pub fn entire() -> Self {
+        Self { lo: f64::NEG_INFINITY, hi: f64::INFINITY }
+    }
+
+    fn rounded_out(lo: f64, hi: f64) -> Self {
+        if lo.is_nan() || hi.is_nan() {
+            return Self::entire();
+        }
+        Self { lo: lo.next_down(), hi: hi.next_up() }
+    }
+
+    pub fn width(&self) -> f64 {
+        self.hi - self.lo
+    }
+
+    pub fn midpoint(&self) -> f64 {
+        0.5 * (self.lo + self.hi)
+    }
+
+    pub fn contains(&self, value: f64) -> bool {
+        self.lo <= value && value <= self.hi
+    }
+
+    pub fn is_subset_of(&self, other: &Self) -> bool {
+        other.lo <= self.lo && self.hi <= other.hi
+    }
+
+    pub fn hull(&self, other: &Self) -> Self {
+        Self { lo: self.lo.min(other.lo), hi: self.hi.max(other.hi) }
+    }
+
+    /// Widens by `amount` on both sides.
+    pub fn inflated(&self, amount: f64) -> Self {
+        Self::rounded_out(self.lo - amount, self.hi + amount)
+    }
+
+    // Range of sin (peak = pi/2) or cos (peak = 0) over the interval: the values at the ends,
+    // and +-1 wherever an extremum peak + k * pi might lie inside (a maximum for even k).
+    // Borderline extrema are included, which only widens the result.
+    fn trig_range(&self, f: fn(f64) -> f64, peak: f64) -> Self {
+        if self.width().is_nan() || self.width() >= 2.0 * PI {
+            return Self::new(-1.0, 1.0);
+        }
+        let (a, b) = (f(self.lo), f(self.hi));
+        let mut lower = a.min(b) - TRIG_ERROR;
+        let mut upper = a.max(b) + TRIG_ERROR;
+
+        let first = ((self.lo - peak) / PI - 1e-9).ceil() as i64;
+        let last = ((self.hi - peak) / PI + 1e-9).floor() as i64;
+        for k in first..=last {
+            if k.rem_euclid(2) == 0 {
+                upper = 1.0;
+            } else {
+                lower = -1.0;
+            }
+        }
+        Self { lo: lower.max(-1.0), hi: upper.min(1.0) }
+    }
+}
+
+impl Add for Interval {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self::rounded_out(self.lo + rhs.lo, self.hi + rhs.hi)
+    }
+}
+
+impl Sub for Interval {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self::rounded_out(self.lo - rhs.hi, self.hi - rhs.lo)
+    }
+}
+
+impl Neg for Interval {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self { lo: -self.hi, hi: -self.lo }
+    }
+}
+
+impl Mul for Interval {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        let products = [self.lo * rhs.lo, self.lo * rhs.hi, self.hi * rhs.lo, self.hi * rhs.hi];
+        if products.iter().any(|p| p.is_nan()) {
+            return Self::entire();
+        }
+        let lo = products.iter().fold(f64::INFINITY, |a, &b| a.min(b));
+        let hi = products.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b));
+        Self::rounded_out(lo, hi)
+    }
+}
+
+impl Div for Interval {
+    type Output = Self;
+    // Unbounded when the divisor contains zero
+    fn div(self, rhs: Self) -> Self {
+        if rhs.contains(0.0) {
+            return Self::entire();
+        }
+        self * Self::rounded_out(1.0 / rhs.hi, 1.0 / rhs.lo)
+    }
+}
+
+impl Scalar for Interval {
+    fn constant(value: f64) -> Self {
+        Self::point(value)
+    }
+
+    fn sin_cos(&self) -> (Self, Self) {
+        (self.trig_range(f64::sin, 0.5 * PI), self.trig_range(f64::cos, 0.0))
+    }
+
+    // Monotone, so the ends map to the ends. Only the part at or above zero is in the domain,
+    // as for squares that came out with a negative lower end.
+    fn sqrt(&self) -> Self {
+        if self.hi < 0.0 || self.lo.is_nan() || self.hi.is_nan() {
+            return Self::entire();
+        }
+        let lo = self.lo.max(0.0);
+        Self { lo: f64::sqrt(lo).next_down().max(0.0), hi: f64::sqrt(self.hi).next_up() }
+    }
+
+    fn value(&self) -> f64 {
+        self.midpoint()
+    }
+}
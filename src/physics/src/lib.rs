@@ -2,11 +2,44 @@ use rand::Rng;
 use wasm_bindgen::prelude::*;
 use serde::{ Serialize, Deserialize };
 use core::ops;
-use std::{ f64::consts::PI, vec };
+use std::{ cell::Cell, f64::consts::PI, rc::Rc, vec };
 use nalgebra::{ DMatrix, DVector, LU };
+use integrators::{
+    AdamsBashforthMoulton,
+    Bdf,
+    BulirschStoer,
+    DormandPrince,
+    Dynamics,
+    Euler,
+    GaussLegendre,
+    Integrator,
+    Leapfrog,
+    RungeKutta4,
+    finite_difference_jacobian,
+    StateSpace,
+    StepStatistics,
+    SymmetricLeapfrog,
+    Taylor,
+    Variational,
+    Verlet,
+};
+use reference::Reference;
+use scalar::{ Dual, Scalar };
+use series::Series;
+use verified::Enclosure;
 // extern crate console_error_panic_hook;
 // use std::panic;
 
+pub mod double_double;
+pub mod integrators;
+pub mod interval;
+pub mod reference;
+pub mod scalar;
+pub mod series;
+pub mod verified;
+#[cfg(test)]
+mod tests;
+
 #[wasm_bindgen]
 #[derive(Serialize, Deserialize, Clone, PartialEq, Copy, Default)]
 pub struct Vec2 {
@@ -20,8 +53,8 @@ impl Vec2 {
         Self { x, y }
     }
     pub fn divide(&mut self, n: f64) {
-        self.x = self.x / n;
-        self.y = self.y / n;
+        self.x /= n;
+        self.y /= n;
     }
     pub fn distance_from(&self, other: Vec2) -> f64 {
         f64::sqrt(f64::powi(self.x - other.x, 2) + f64::powi(self.y - other.y, 2))
@@ -78,12 +111,28 @@ pub struct Rod {
     pub length: f64,
     pub mass: f64,
     pub color: u32,
+    // A rod with stiffness is a spring, and its length moves. Without it the rod is rigid.
+    pub rest_length: f64,
+    pub stiffness: f64, // Force per unit of stretch
+    pub damping: f64, // Force per unit of stretch rate
+    pub length_rate: f64,
 }
 #[wasm_bindgen]
 impl Rod {
     #[wasm_bindgen(constructor)]
     pub fn new(length: f64, mass: f64, color: u32) -> Rod {
-        Rod { length, mass, color }
+        Rod {
+            length,
+            mass,
+            color,
+            rest_length: length,
+            stiffness: 0.0,
+            damping: 0.0,
+            length_rate: 0.0,
+        }
+    }
+    pub fn is_elastic(&self) -> bool {
+        self.stiffness > 0.0
     }
     pub fn update_length(&mut self, length: f64) {
         self.length = length;
@@ -100,6 +149,73 @@ pub struct Trail {
     pub color: u32,
 }
 
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum EventKind {
+    BobBottom, // A link passed straight down under its pivot
+    LinkFlip, // A link passed straight up over its pivot
+    Custom, // A function added with add_event_function changed sign
+}
+
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Event {
+    pub kind: EventKind,
+    pub index: usize, // Ball index, or the id returned by add_event_function for Custom
+    pub time: f64, // Interpolated simulation time of the crossing
+    pub direction: i32, // 1 if the function went from negative to positive, -1 otherwise
+}
+
+// User event function of (time, thetas, omegas); an event fires when it changes sign
+pub type EventFunction = Rc<dyn Fn(f64, &[f64], &[f64]) -> f64>;
+
+// User pivot trajectory: position, velocity and acceleration of the pivot at a time
+pub type PivotFunction = Rc<dyn Fn(f64) -> (Vec2, Vec2, Vec2)>;
+
+// User gravity history: the uniform field and its rate of change at a time
+pub type GravityFunction = Rc<dyn Fn(f64) -> (Vec2, Vec2)>;
+
+// Call a JS function of time returning [x0, y0, x1, y1, ...] as N vectors, reading missing
+// or non-numeric entries as 0
+fn call_vectors<const N: usize>(callback: &js_sys::Function, t: f64) -> [Vec2; N] {
+    let result = callback.call1(&JsValue::NULL, &JsValue::from(t)).unwrap_or(JsValue::NULL);
+    let values = js_sys::Array::from(&result);
+    let entry = |i: usize| values.get(i as u32).as_f64().unwrap_or(0.0);
+    std::array::from_fn(|k| Vec2::new(entry(2 * k), entry(2 * k + 1)))
+}
+
+// Prescribed motion of the pivot, see set_pivot_motion
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum PivotMotion {
+    Fixed,
+    Vertical, // y = A * sin(w * t)
+    Horizontal, // x = A * sin(w * t)
+    Circular, // (x, y) = A * (cos(w * t), sin(w * t))
+    Custom, // Set with set_pivot_function
+}
+
+// Cart the chain hangs from in cart-pole mode, sliding on a horizontal rail through the
+// origin. Its position is a generalized coordinate of its own, after the link angles.
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Cart {
+    pub position: f64,
+    pub velocity: f64,
+    pub mass: f64,
+    pub friction: f64, // Viscous rail friction, force per velocity
+    pub force: f64, // Applied horizontal force, the control input
+}
+
+// Point mass pulling the chain with inverse-square gravity, on top of the uniform field:
+// a mass m at distance r from it is pulled toward it with strength * m / r^2
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct GravitySource {
+    pub position: Vec2,
+    pub strength: f64, // G times the mass of the source
+}
+
 #[wasm_bindgen]
 #[derive(Serialize, Deserialize, PartialEq, Clone)]
 pub struct Ball {
@@ -111,10 +227,29 @@ pub struct Ball {
     pub radius: i32,
     pub mass: f64,
     pub color: u32,
+    // Friction in the joint above the ball, against the rotation relative to the link above
+    pub viscous_friction: f64, // Torque per angular velocity
+    pub coulomb_friction: f64, // Dry friction torque while slipping
+    // Motor in the same joint, turning the ball's link against the link above
+    pub torque: f64,
+    torque_schedule: Vec<(f64, f64)>, // (time, torque) points, replacing torque if not empty
+    // Stops on the same joint's angle relative to the link above, in [-PI, PI], and the
+    // fraction of the joint's rate it bounces back with off them
+    pub min_angle: f64,
+    pub max_angle: f64,
+    pub restitution: f64,
+    // Where the ball's chain hangs from, relative to the support (the pivot the prescribed
+    // motion or the cart moves), if the ball starts a chain of its own instead of hanging
+    // from another ball. The first ball always starts a chain, at the support if None.
+    pivot: Option<Vec2>,
+    // The earlier ball the link hangs from if not the one just before it, so that a ball
+    // can carry several links and a chain branches into a tree
+    parent: Option<usize>,
 }
 #[wasm_bindgen]
 impl Ball {
     #[wasm_bindgen(constructor)]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         px: f64,
         py: f64,
@@ -136,6 +271,15 @@ impl Ball {
             color,
             rod: Rod::new(rl, rm, rc),
             trail: vec![],
+            viscous_friction: 0.0,
+            coulomb_friction: 0.0,
+            torque: 0.0,
+            torque_schedule: vec![],
+            min_angle: f64::NEG_INFINITY,
+            max_angle: f64::INFINITY,
+            restitution: 1.0,
+            pivot: None,
+            parent: None,
         }
     }
 
@@ -162,13 +306,35 @@ pub enum Implementation {
     RK4,
     Verlet, // Verlet integration (position-based)
     Leapfrog, // Leapfrog integration (velocity half-steps)
+    DormandPrince, // Adaptive RK5(4) with embedded error estimate
+    GaussLegendre2, // Implicit 2-stage Gauss–Legendre (order 4, symplectic)
+    GaussLegendre3, // Implicit 3-stage Gauss–Legendre (order 6, symplectic)
+    BulirschStoer, // Adaptive Gragg–Bulirsch–Stoer extrapolation
+    AdamsBashforthMoulton, // 4th order multistep predictor–corrector, one evaluation per step
+    Variational, // Discrete variational integrator from the chain Lagrangian (symplectic)
+    Taylor, // Adaptive Taylor series method of selectable order, coefficients by automatic differentiation
+    BackwardEuler, // Implicit BDF1 with Newton iterations, for stiff chains (damps)
+    Bdf2, // Implicit variable-step BDF2 with Newton iterations, for stiff chains
+    SymmetricLeapfrog, // Leapfrog with an implicit closing half kick, reversible for the chain
+}
+
+#[wasm_bindgen]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum EnergyLimit {
+    Scale, // Scale velocities down when the energy grew (never up)
+    Projection, // Minimal correction of (theta, omega) back onto the initial energy, both ways
 }
 
 #[wasm_bindgen]
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Universe {
     balls: Vec<Ball>,
-    gravity: f64,
+    gravity: Vec2, // Uniform field, positive y is down
+    gravity_amplitude: f64, // Relative modulation of the uniform field, see set_gravity_modulation
+    gravity_frequency: f64, // Angular frequency
+    #[serde(skip)]
+    gravity_function: Option<GravityFunction>,
+    gravity_source: Option<GravitySource>,
     mass_calculation: bool,
     show_trails: bool,
     is_paused: bool,
@@ -178,6 +344,58 @@ pub struct Universe {
     initial_energy: f64,
     default_mass: f64,
     limit_total_energy: bool,
+    energy_limit: EnergyLimit,
+    last_energy_correction: f64,
+    total_energy_correction: f64,
+    abs_tolerance: f64,
+    rel_tolerance: f64,
+    implicit_tolerance: f64,
+    implicit_max_iterations: u32,
+    extrapolation_order: u32,
+    taylor_order: u32,
+    time: f64,
+    fixed_timestep: bool,
+    fixed_dt: f64,
+    accumulator: f64,
+    interpolation_factor: f64,
+    previous_thetas: Vec<f64>, // Angles before the last fixed step, for render interpolation
+    previous_pivot: Vec2, // Pivot before the last fixed step
+    detect_bottom_passes: bool,
+    detect_link_flips: bool,
+    #[serde(skip)]
+    event_functions: Vec<(usize, EventFunction)>,
+    next_event_function_id: usize,
+    events: Vec<Event>, // Events found during the last time_step
+    time_reversed: bool,
+    reference: Option<Reference>, // High-precision shadow run, see start_reference
+    divergence_threshold: f64,
+    enclosure: Option<Enclosure>, // Verified interval run, see start_enclosure
+    enclosure_threshold: f64,
+    rod_inertia: bool, // Rods are uniform rigid bodies of rod.mass instead of massless
+    stick_velocity: f64, // Relative angular velocity below which Coulomb friction sticks
+    // Medium the bobs move through, for drag on spheres of the ball radius
+    medium_density: f64,
+    medium_viscosity: f64, // Dynamic viscosity
+    drag_coefficient: f64,
+    dissipated_energy: f64, // Energy taken out by friction and drag since initial_energy was set
+    pivot_motion: PivotMotion,
+    pivot_amplitude: f64,
+    pivot_frequency: f64, // Angular frequency
+    #[serde(skip)]
+    pivot_function: Option<PivotFunction>,
+    pivot_work: f64, // Energy the moving pivot put into the chain since initial_energy was set
+    cart: Option<Cart>, // Cart-pole mode, see enable_cart
+    actuator_work: f64, // Work of the motors and the cart force since initial_energy was set
+    field_work: f64, // Energy the changing gravity put into the chain since initial_energy was set
+    // Built from `implementation` on demand, unless a custom one was set
+    #[serde(skip)]
+    integrator: Option<Box<dyn Integrator>>,
+    custom_integrator: bool,
+}
+impl Default for Universe {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 #[wasm_bindgen]
 impl Universe {
@@ -194,7 +412,11 @@ impl Universe {
         let ball2 = Ball::new(200.0, 0.0, 0.0, PI / 2.0, 100.0, 10.0, 0x0f0f0f, 10, 10.0, 0x0000ff);
         let mut universe = Universe {
             balls: vec![ball1, ball2],
-            gravity: 9.8,
+            gravity: Vec2::new(0.0, 9.8),
+            gravity_amplitude: 0.0,
+            gravity_frequency: 0.0,
+            gravity_function: None,
+            gravity_source: None,
             implementation: Implementation::Euler,
             speed: 1.0 / 20.0,
             mass_calculation: true,
@@ -204,6 +426,48 @@ impl Universe {
             initial_energy: 0.0, // Will be calculated next
             default_mass: 10.0, // Default mass used when mass_calculation is false
             limit_total_energy: false, // Enable energy limiting off by default
+            energy_limit: EnergyLimit::Scale, // Projection is opt-in with set_energy_limit
+            last_energy_correction: 0.0,
+            total_energy_correction: 0.0,
+            abs_tolerance: 1e-8,
+            rel_tolerance: 1e-6,
+            implicit_tolerance: 1e-14,
+            implicit_max_iterations: 50,
+            extrapolation_order: 8,
+            taylor_order: 20,
+            time: 0.0,
+            fixed_timestep: false,
+            fixed_dt: 0.01,
+            accumulator: 0.0,
+            interpolation_factor: 1.0,
+            previous_thetas: vec![],
+            previous_pivot: Vec2::new(0.0, 0.0),
+            detect_bottom_passes: false,
+            detect_link_flips: false,
+            event_functions: vec![],
+            next_event_function_id: 0,
+            events: vec![],
+            time_reversed: false,
+            reference: None,
+            divergence_threshold: 1e-3,
+            enclosure: None,
+            enclosure_threshold: 0.1,
+            rod_inertia: false,
+            stick_velocity: 0.01,
+            medium_density: 0.0, // Vacuum
+            medium_viscosity: 0.0,
+            drag_coefficient: 0.47, // Smooth sphere
+            dissipated_energy: 0.0,
+            pivot_motion: PivotMotion::Fixed,
+            pivot_amplitude: 0.0,
+            pivot_frequency: 0.0,
+            pivot_function: None,
+            pivot_work: 0.0,
+            cart: None,
+            actuator_work: 0.0,
+            field_work: 0.0,
+            integrator: None,
+            custom_integrator: false,
         };
         // Calculate initial total energy (potential + kinetic)
         universe.initial_energy =
@@ -214,35 +478,90 @@ impl Universe {
         if self.balls.is_empty() || self.is_paused {
             return 1;
         }
+        self.events.clear();
 
         if self.balls.len() > self.max_balls {
             // cutoff for Euler method, remove extras
             self.balls.truncate(self.max_balls);
+            self.invalidate_history();
         }
 
         // Calculate the effective speed multiplier
-        let speed_multiplier = self.speed * 2.0;
-
-        // Instead of scaling dt directly (which causes instability at high speeds),
-        // we take multiple smaller steps to maintain numerical stability
-        let steps = (speed_multiplier.abs() * 50.0).ceil().max(1.0) as usize;
-        let sub_dt = (dt * speed_multiplier) / (steps as f64);
+        let mut speed_multiplier = self.speed * 2.0;
+        if self.time_reversed && self.integrator().is_time_symmetric() {
+            // Only integrators that can retrace their own steps run backwards
+            speed_multiplier = -speed_multiplier;
+        }
 
-        // Perform multiple substeps with smaller dt
-        for _ in 0..steps {
-            let result = self.single_physics_step(sub_dt);
+        if self.fixed_timestep {
+            // Frame-rate independent: always step by fixed_dt, render interpolates the rest
+            let result = self.fixed_physics_steps(dt * speed_multiplier);
             if result != 0 {
-                return result; // Early exit if NaN detected
+                return result;
+            }
+        } else if self.integrator().is_adaptive() {
+            // The adaptive integrator picks its own step sizes over the whole frame
+            let result = self.single_physics_step(dt * speed_multiplier);
+            if result != 0 {
+                return result;
+            }
+        } else {
+            // Instead of scaling dt directly (which causes instability at high speeds),
+            // we take multiple smaller steps to maintain numerical stability
+            let steps = (speed_multiplier.abs() * 50.0).ceil().max(1.0) as usize;
+            let sub_dt = (dt * speed_multiplier) / (steps as f64);
+
+            // Perform multiple substeps with smaller dt
+            for _ in 0..steps {
+                let result = self.single_physics_step(sub_dt);
+                if result != 0 {
+                    return result; // Early exit if NaN detected
+                }
             }
         }
 
+        self.advance_reference();
+        self.advance_enclosure();
+
         // Add trail points only once per frame (not per substep)
         if self.show_trails {
             for ball in &mut self.balls {
                 ball.add_trail_point(ball.pos, ball.color, 250);
             }
         }
-        return 0;
+        0
+    }
+
+    // Advance by whole fixed_dt steps and carry the leftover time to the next frame, so the
+    // same steps are taken whatever the display refresh rate is
+    fn fixed_physics_steps(&mut self, frame_time: f64) -> u8 {
+        // Beyond this we can't catch up, drop the backlog instead of spiralling
+        const MAX_STEPS_PER_FRAME: usize = 10_000;
+        // Frame times rarely add up to exact multiples of fixed_dt, don't miss a step by rounding
+        let threshold = self.fixed_dt * (1.0 - 1e-9);
+
+        self.accumulator += frame_time;
+        let mut steps = 0;
+        while self.accumulator.abs() >= threshold {
+            if steps == MAX_STEPS_PER_FRAME {
+                self.accumulator = 0.0;
+                break;
+            }
+            let step = self.fixed_dt.copysign(self.accumulator);
+            self.previous_thetas = self.balls
+                .iter()
+                .map(|ball| ball.theta)
+                .collect();
+            self.previous_pivot = self.pivot_position(self.time);
+            let result = self.single_physics_step(step);
+            if result != 0 {
+                return result; // Early exit if NaN detected
+            }
+            self.accumulator -= step;
+            steps += 1;
+        }
+        self.interpolation_factor = (self.accumulator.abs() / self.fixed_dt).min(1.0);
+        0
     }
 
     // Normalize angle to [-PI, PI] range for better floating point precision
@@ -257,383 +576,1645 @@ impl Universe {
     }
 
     // Calculate total potential energy of the system
-    // U = -sum(m_i * g * y_i) where y_i is the vertical position of each mass
+    // U = -sum(m_i * g . r_i) - sum(strength * m_i / |r_i - source|) + springs, over the
+    // masses at r_i, with the uniform part measured from the pivot
     fn calculate_potential_energy(&self) -> f64 {
-        let mut potential = 0.0;
-        let mut y_cumulative = 0.0;
+        let (thetas, _) = self.coordinates();
+        self.potential_energy(&thetas)
+    }
 
-        for i in 0..self.balls.len() {
-            // Vertical position is cumulative (each mass hangs from the previous)
-            y_cumulative += self.balls[i].rod.length * f64::cos(self.balls[i].theta);
+    fn potential_energy(&self, thetas: &DVector<f64>) -> f64 {
+        self.potential_energy_at(&self.time, thetas.as_slice())
+    }
+
+    // Potential energy at time t in any arithmetic, which the field can depend on
+    fn potential_energy_at<T: Scalar>(&self, t: &T, thetas: &[T]) -> T {
+        let distribution = self.mass_distribution();
+        let lengths = self.link_lengths(thetas);
+        let (gx, gy) = self.uniform_gravity(t);
+        let mut potential = T::constant(0.0);
+
+        for (i, ball) in self.balls.iter().enumerate() {
+            // Link i moves the masses along and below it, weighted by moment_i
+            let (sin, cos) = thetas[i].sin_cos();
+            potential =
+                potential -
+                T::constant(distribution.moment[i]) *
+                    lengths[i].clone() *
+                    (gx.clone() * sin + gy.clone() * cos);
+            if ball.rod.is_elastic() {
+                let stretch = lengths[i].clone() - T::constant(ball.rod.rest_length);
+                potential =
+                    potential + T::constant(0.5 * ball.rod.stiffness) * stretch.clone() * stretch;
+            }
+        }
+        if let (Some(cart), Some(x)) = (&self.cart, self.cart_coordinate()) {
+            // Sliding the cart moves every mass along the rail
+            let mass = T::constant(cart.mass + distribution.total);
+            potential = potential - mass * gx * thetas[x].clone();
+        }
 
-            // Potential energy (negative because positive y is down)
-            let mass = if self.mass_calculation { self.balls[i].mass } else { self.default_mass };
-            potential -= mass * self.gravity * y_cumulative;
+        if let Some(source) = &self.gravity_source {
+            for ((x, y), mass) in self.mass_points(t, thetas) {
+                let dx = T::constant(source.position.x) - x;
+                let dy = T::constant(source.position.y) - y;
+                let distance = (dx.clone() * dx + dy.clone() * dy).sqrt();
+                potential = potential - T::constant(source.strength * mass) / distance;
+            }
         }
         potential
     }
 
     // Calculate total kinetic energy of the system
     fn calculate_kinetic_energy(&self) -> f64 {
-        let mut kinetic = 0.0;
-
-        for i in 0..self.balls.len() {
-            // For a multi-pendulum, kinetic energy includes contributions from all previous segments
-            let mut vx = 0.0;
-            let mut vy = 0.0;
+        let (thetas, theta_dots) = self.coordinates();
+        self.kinetic_energy(&thetas, &theta_dots)
+    }
 
-            for j in 0..=i {
-                vx +=
-                    self.balls[j].rod.length * self.balls[j].omega * f64::cos(self.balls[j].theta);
-                vy +=
-                    -self.balls[j].rod.length * self.balls[j].omega * f64::sin(self.balls[j].theta);
-            }
+    fn kinetic_energy(&self, thetas: &DVector<f64>, theta_dots: &DVector<f64>) -> f64 {
+        // T = 1/2 * omega^T * M * omega covers the bobs, the rods' centres and their spin
+        0.5 * theta_dots.dot(&(self.mass_matrix(thetas) * theta_dots))
+    }
 
-            let v_squared = vx * vx + vy * vy;
+    // Energy the system should have right now: the energy at the last edit, less what
+    // friction, drag and impacts took out, plus the work of the moving pivot, the motors and
+    // a changing gravity field since then
+    fn target_energy(&self) -> f64 {
+        self.initial_energy - self.dissipated_energy +
+            self.pivot_work +
+            self.actuator_work +
+            self.field_work
+    }
 
-            let mass = if self.mass_calculation { self.balls[i].mass } else { self.default_mass };
-            kinetic += 0.5 * mass * v_squared;
-        }
-        kinetic
+    // Keep the total energy at target_energy with the selected EnergyLimit mode
+    fn limit_energy(&mut self) {
+        let target = self.target_energy();
+        let correction = match self.energy_limit {
+            EnergyLimit::Scale => self.constrain_velocities(target),
+            EnergyLimit::Projection => self.project_energy(target),
+        };
+        self.last_energy_correction = correction;
+        self.total_energy_correction += correction;
     }
 
-    // Constrain velocities based on energy conservation
-    fn constrain_velocities(&mut self, initial_energy: f64) {
+    // Constrain velocities based on energy conservation. Returns the size of the change.
+    fn constrain_velocities(&mut self, initial_energy: f64) -> f64 {
         let current_potential = self.calculate_potential_energy();
         let max_kinetic = initial_energy - current_potential;
 
         // If we have negative kinetic energy, we have a problem
         if max_kinetic < 0.0 {
-            return;
+            return 0.0;
         }
 
         let current_kinetic = self.calculate_kinetic_energy();
 
         // If kinetic energy exceeds what's possible, scale down velocities
+        let mut correction = 0.0;
         if current_kinetic > max_kinetic && current_kinetic > 0.0 {
             let scale_factor = f64::sqrt(max_kinetic / current_kinetic);
-            for i in 0..self.balls.len() {
-                self.balls[i].omega *= scale_factor;
+            let (thetas, theta_dots) = self.coordinates();
+            correction = (&theta_dots * (scale_factor - 1.0)).norm_squared();
+            self.set_coordinates(&thetas, &(theta_dots * scale_factor));
+        }
+        f64::sqrt(correction)
+    }
+
+    // Pull the state back onto the surface E(theta, omega) = target, in both directions.
+    // Newton steps along the energy gradient give the smallest correction (in the
+    // Euclidean norm of (theta, omega)) that removes the energy error to first order.
+    // Returns the size of the correction.
+    fn project_energy(&mut self, target: f64) -> f64 {
+        const MAX_ITERATIONS: usize = 5;
+
+        let n = self.coordinate_count();
+        let (thetas, theta_dots) = self.coordinates();
+        let mut state = DVector::from_element(2 * n, 0.0);
+        state.rows_mut(0, n).copy_from(&thetas);
+        state.rows_mut(n, n).copy_from(&theta_dots);
+        let original = state.clone();
+        let energy = |state: &DVector<f64>| {
+            let thetas = state.rows(0, n).into_owned();
+            let theta_dots = state.rows(n, n).into_owned();
+            self.potential_energy(&thetas) + self.kinetic_energy(&thetas, &theta_dots)
+        };
+
+        for _ in 0..MAX_ITERATIONS {
+            let residual = energy(&state) - target;
+            if residual.abs() <= 1e-12 * f64::max(1.0, target.abs()) {
+                break;
+            }
+
+            // Central difference gradient of the total energy
+            let mut gradient = DVector::from_element(2 * n, 0.0);
+            for i in 0..2 * n {
+                let h = 1e-6 * f64::max(1.0, state[i].abs());
+                let mut forward = state.clone();
+                let mut backward = state.clone();
+                forward[i] += h;
+                backward[i] -= h;
+                gradient[i] = (energy(&forward) - energy(&backward)) / (2.0 * h);
             }
+
+            let norm_squared = gradient.norm_squared();
+            if norm_squared == 0.0 || !norm_squared.is_finite() {
+                break; // At an energy extremum there is no direction to correct along
+            }
+            state -= gradient * (residual / norm_squared);
         }
+
+        self.set_coordinates(&state.rows(0, n).into_owned(), &state.rows(n, n).into_owned());
+        self.update_positions();
+        (state - original).norm()
     }
 
     // Recalculate and store the initial energy (call after modifying the system)
     fn update_initial_energy(&mut self) {
         self.initial_energy = self.calculate_potential_energy() + self.calculate_kinetic_energy();
+        self.dissipated_energy = 0.0;
+        self.pivot_work = 0.0;
+        self.actuator_work = 0.0;
+        self.field_work = 0.0;
     }
 
-    fn single_physics_step(&mut self, dt: f64) -> u8 {
-        if self.implementation == Implementation::Euler {
-            let thetas: DVector<f64> = DVector::from_iterator(
-                self.balls.len(),
-                self.balls.iter().map(|ball| ball.theta)
-            );
-            let theta_dots: DVector<f64> = DVector::from_iterator(
-                self.balls.len(),
-                self.balls.iter().map(|ball| ball.omega)
-            );
-
-            // Calculate accelerations using the matrix method
-            let (_, theta_ddots) = self.calculate_accelerations(&thetas, &theta_dots);
+    // Recalculate every ball position from the angles (cumulative from each chain's pivot)
+    fn update_positions(&mut self) {
+        let pivot = self.pivot_position(self.time);
+        for i in 0..self.balls.len() {
+            let start = match self.parent(i) {
+                Some(parent) => self.balls[parent].pos,
+                None => pivot + self.balls[i].pivot.unwrap_or_default(),
+            };
+            let ball = &mut self.balls[i];
+            ball.pos.x = start.x + ball.rod.length * f64::sin(ball.theta);
+            ball.pos.y = start.y + ball.rod.length * f64::cos(ball.theta);
+        }
+    }
 
-            // Check for NaN before updating
-            if theta_ddots.iter().any(|&x| x.is_nan()) {
-                return 1;
-            }
+    // The ball whose link ball i's link hangs from, None if it starts a chain and hangs
+    // from the chain's pivot. Parents come before the balls hanging from them.
+    fn parent(&self, index: usize) -> Option<usize> {
+        let ball = &self.balls[index];
+        if index == 0 || ball.pivot.is_some() {
+            None
+        } else {
+            Some(ball.parent.unwrap_or(index - 1))
+        }
+    }
 
-            // Euler integration: update velocities and positions
-            for i in 0..self.balls.len() {
-                self.balls[i].omega += theta_ddots[i] * dt;
-                self.balls[i].theta += self.balls[i].omega * dt;
+    // The balls that start a chain, in order
+    fn chain_roots(&self) -> Vec<usize> {
+        (0..self.balls.len()).filter(|&i| self.parent(i).is_none()).collect()
+    }
 
-                // Normalize angle to [-PI, PI] for better floating point precision
-                // self.balls[i].theta = Self::normalize_angle(self.balls[i].theta);
+    fn parents(&self) -> Vec<Option<usize>> {
+        (0..self.balls.len()).map(|i| self.parent(i)).collect()
+    }
 
-                // Calculate positions (cumulative from origin)
-                let mut x = 0.0;
-                let mut y = 0.0;
-                for j in 0..=i {
-                    x += self.balls[j].rod.length * f64::sin(self.balls[j].theta);
-                    y += self.balls[j].rod.length * f64::cos(self.balls[j].theta);
-                }
-                self.balls[i].pos.x = x;
-                self.balls[i].pos.y = y;
+    // Whether link k hangs (directly or further down) from link i, or is link i
+    fn hangs_from(parents: &[Option<usize>], k: usize, i: usize) -> bool {
+        let mut link = Some(k);
+        while let Some(j) = link {
+            if j <= i {
+                return j == i;
             }
-        } else if self.implementation == Implementation::RK4 {
-            let thetas: DVector<f64> = DVector::from_iterator(
-                self.balls.len(),
-                self.balls.iter().map(|ball| ball.theta)
-            );
-            let theta_dots: DVector<f64> = DVector::from_iterator(
-                self.balls.len(),
-                self.balls.iter().map(|ball| ball.omega)
-            );
+            link = parents[j];
+        }
+        false
+    }
 
-            let k1 = self.calculate_accelerations(&thetas, &theta_dots);
-            let k2 = self.calculate_accelerations(
-                &(thetas.clone() + &k1.0 * (0.5 * dt)),
-                &(theta_dots.clone() + &k1.1 * (0.5 * dt))
-            );
-            let k3 = self.calculate_accelerations(
-                &(thetas.clone() + &k2.0 * (0.5 * dt)),
-                &(theta_dots.clone() + &k2.1 * (0.5 * dt))
-            );
-            let k4 = self.calculate_accelerations(
-                &(thetas.clone() + &k3.0 * (1.0 * dt)),
-                &(theta_dots.clone() + &k3.1 * (1.0 * dt))
-            );
+    fn build_integrator(&self) -> Box<dyn Integrator> {
+        match self.implementation {
+            Implementation::Euler => Box::new(Euler),
+            Implementation::RK4 => Box::new(RungeKutta4),
+            Implementation::Verlet => Box::new(Verlet),
+            Implementation::Leapfrog => Box::new(Leapfrog),
+            Implementation::SymmetricLeapfrog => Box::new(SymmetricLeapfrog),
+            Implementation::DormandPrince =>
+                Box::new(DormandPrince::new(self.abs_tolerance, self.rel_tolerance)),
+            Implementation::GaussLegendre2 =>
+                Box::new(
+                    GaussLegendre::two_stage(self.implicit_tolerance, self.implicit_max_iterations)
+                ),
+            Implementation::GaussLegendre3 =>
+                Box::new(
+                    GaussLegendre::three_stage(self.implicit_tolerance, self.implicit_max_iterations)
+                ),
+            Implementation::BulirschStoer =>
+                Box::new(
+                    BulirschStoer::new(
+                        self.abs_tolerance,
+                        self.rel_tolerance,
+                        self.extrapolation_order as usize
+                    )
+                ),
+            Implementation::AdamsBashforthMoulton => Box::new(AdamsBashforthMoulton::new()),
+            Implementation::Variational =>
+                Box::new(Variational::new(self.implicit_tolerance, self.implicit_max_iterations)),
+            Implementation::Taylor =>
+                Box::new(
+                    Taylor::new(self.taylor_order as usize, self.abs_tolerance, self.rel_tolerance)
+                ),
+            Implementation::BackwardEuler =>
+                Box::new(Bdf::backward_euler(self.implicit_tolerance, self.implicit_max_iterations)),
+            Implementation::Bdf2 =>
+                Box::new(Bdf::bdf2(self.implicit_tolerance, self.implicit_max_iterations)),
+        }
+    }
 
-            // Calculate deltas: (k1 + 2*k2 + 2*k3 + k4) * dt/6
-            let theta_deltas = (&k1.0 + &k2.0 * 2.0 + &k3.0 * 2.0 + &k4.0) * (dt / 6.0);
-            let theta_dot_deltas = (&k1.1 + &k2.1 * 2.0 + &k3.1 * 2.0 + &k4.1) * (dt / 6.0);
-            // Update balls
-            for i in 0..self.balls.len() {
-                self.balls[i].theta += theta_deltas[i];
-                self.balls[i].omega += theta_dot_deltas[i];
-
-                // Normalize angle to [-PI, PI] for better floating point precision
-                // self.balls[i].theta = Self::normalize_angle(self.balls[i].theta);
-
-                // Calculate positions (cumulative from origin)
-                let mut x = 0.0;
-                let mut y = 0.0;
-                for j in 0..=i {
-                    x += self.balls[j].rod.length * f64::sin(self.balls[j].theta);
-                    y += self.balls[j].rod.length * f64::cos(self.balls[j].theta);
-                }
-                self.balls[i].pos.x = x;
-                self.balls[i].pos.y = y;
-            }
-        } else if self.implementation == Implementation::Verlet {
-            // Verlet integration (position-based with previous and current positions)
-            // Based on: x(t+dt) = x(t) + v(t)*dt + 0.5*a(t)*dt^2
-            // Then: v(t+dt) = 0.5*(a(t) + a(t+dt))*dt
-            let thetas: DVector<f64> = DVector::from_iterator(
-                self.balls.len(),
-                self.balls.iter().map(|ball| ball.theta)
-            );
-            let theta_dots: DVector<f64> = DVector::from_iterator(
-                self.balls.len(),
-                self.balls.iter().map(|ball| ball.omega)
-            );
+    fn integrator(&mut self) -> &mut Box<dyn Integrator> {
+        if self.integrator.is_none() {
+            self.integrator = Some(self.build_integrator());
+        }
+        self.integrator.as_mut().unwrap()
+    }
 
-            // Calculate current accelerations
-            let (_, theta_ddots) = self.calculate_accelerations(&thetas, &theta_dots);
+    // Forget what was carried over from previous steps (multistep history, step size
+    // proposals, render interpolation). Call whenever the state is changed outside of a step.
+    fn invalidate_history(&mut self) {
+        self.restart_integrator();
+        self.previous_thetas.clear();
+        self.restart_shadow_runs();
+    }
 
-            if theta_ddots.iter().any(|&x| x.is_nan()) {
-                return 1;
-            }
+    // Restart the reference run and the enclosure from the current state
+    fn restart_shadow_runs(&mut self) {
+        if let Some(reference) = &self.reference {
+            // Edits aren't part of the dynamics, compare from here on
+            self.reference = Some(reference.restart(self));
+        }
+        if let Some(enclosure) = &self.enclosure {
+            self.enclosure = Some(enclosure.restart(self));
+        }
+    }
 
-            // Update positions: theta_new = theta + omega*dt + 0.5*alpha*dt^2
-            let mut new_thetas = DVector::from_element(self.balls.len(), 0.0);
-            for i in 0..self.balls.len() {
-                new_thetas[i] = thetas[i] + theta_dots[i] * dt + 0.5 * theta_ddots[i] * dt * dt;
-            }
+    // Bring the reference run and the enclosure up to an impact and stop them there, as
+    // neither can follow the chain through it
+    fn interrupt_shadow_runs(&mut self) {
+        self.advance_reference();
+        self.advance_enclosure();
+        if let Some(reference) = &mut self.reference {
+            reference.interrupt();
+        }
+        if let Some(enclosure) = &mut self.enclosure {
+            enclosure.interrupt();
+        }
+    }
 
-            // Calculate new accelerations at new positions
-            let (_, theta_ddots_new) = self.calculate_accelerations(&new_thetas, &theta_dots);
+    // Drop the integrator's multistep history and step size proposals only, for inputs that
+    // change the dynamics but not the state
+    fn restart_integrator(&mut self) {
+        if let Some(integrator) = &mut self.integrator {
+            integrator.reset();
+        }
+    }
 
-            if theta_ddots_new.iter().any(|&x| x.is_nan()) {
-                return 1;
+    // Bring the reference run to the current time and compare. A reference run that
+    // fails is stopped.
+    fn advance_reference(&mut self) {
+        if let Some(mut reference) = self.reference.take() {
+            if reference.advance(self) {
+                reference.compare(self, self.divergence_threshold);
+                self.reference = Some(reference);
             }
+        }
+    }
 
-            // Update velocities: omega_new = omega + 0.5*(alpha_old + alpha_new)*dt
-            for i in 0..self.balls.len() {
-                self.balls[i].omega =
-                    theta_dots[i] + 0.5 * (theta_ddots[i] + theta_ddots_new[i]) * dt;
-                self.balls[i].theta = new_thetas[i];
+    fn advance_enclosure(&mut self) {
+        if let Some(mut enclosure) = self.enclosure.take() {
+            enclosure.advance(self, self.enclosure_threshold);
+            self.enclosure = Some(enclosure);
+        }
+    }
 
-                // Normalize angle to [-PI, PI] for better floating point precision
-                self.balls[i].theta = Self::normalize_angle(self.balls[i].theta);
+    // Drop the built-in integrator so it is rebuilt with the current settings
+    fn rebuild_integrator(&mut self) {
+        if !self.custom_integrator {
+            self.integrator = None;
+        }
+    }
 
-                // Calculate positions (cumulative from origin)
-                let mut x = 0.0;
-                let mut y = 0.0;
-                for j in 0..=i {
-                    x += self.balls[j].rod.length * f64::sin(self.balls[j].theta);
-                    y += self.balls[j].rod.length * f64::cos(self.balls[j].theta);
-                }
-                self.balls[i].pos.x = x;
-                self.balls[i].pos.y = y;
-            }
-        } else if self.implementation == Implementation::Leapfrog {
-            // Leapfrog integration (velocity half-steps)
-            // Based on: v(t+dt/2) = v(t) + a(t)*dt/2
-            //           x(t+dt) = x(t) + v(t+dt/2)*dt
-            //           a(t+dt) = acceleration at new position
-            //           v(t+dt) = v(t+dt/2) + a(t+dt)*dt/2
-            let thetas: DVector<f64> = DVector::from_iterator(
-                self.balls.len(),
-                self.balls.iter().map(|ball| ball.theta)
-            );
-            let theta_dots: DVector<f64> = DVector::from_iterator(
-                self.balls.len(),
-                self.balls.iter().map(|ball| ball.omega)
-            );
+    // Number of generalized coordinates: the link angles, the lengths of the elastic rods,
+    // then the cart position in cart-pole mode
+    fn coordinate_count(&self) -> usize {
+        let elastic = self.balls
+            .iter()
+            .filter(|ball| ball.rod.is_elastic())
+            .count();
+        self.balls.len() + elastic + usize::from(self.cart.is_some())
+    }
 
-            // Step 1: Half-step velocity update
-            let (_, theta_ddots) = self.calculate_accelerations(&thetas, &theta_dots);
+    // Generalized coordinates and their rates, [thetas, lengths, x] and [omegas, length
+    // rates, v]. Everywhere below, thetas and theta_dots stand for these.
+    fn coordinates(&self) -> (DVector<f64>, DVector<f64>) {
+        let size = self.coordinate_count();
+        let elastic = || self.balls.iter().filter(|ball| ball.rod.is_elastic());
+        let thetas = self.balls
+            .iter()
+            .map(|ball| ball.theta)
+            .chain(elastic().map(|ball| ball.rod.length))
+            .chain(self.cart.map(|cart| cart.position));
+        let theta_dots = self.balls
+            .iter()
+            .map(|ball| ball.omega)
+            .chain(elastic().map(|ball| ball.rod.length_rate))
+            .chain(self.cart.map(|cart| cart.velocity));
+        (DVector::from_iterator(size, thetas), DVector::from_iterator(size, theta_dots))
+    }
 
-            // Check for NaN before updating
-            if theta_ddots.iter().any(|&x| x.is_nan()) {
-                return 1;
+    fn set_coordinates(&mut self, thetas: &DVector<f64>, theta_dots: &DVector<f64>) {
+        let stretches = self.stretch_coordinates();
+        let cart_coordinate = self.cart_coordinate();
+        for (i, ball) in self.balls.iter_mut().enumerate() {
+            ball.theta = thetas[i];
+            ball.omega = theta_dots[i];
+            if let Some(index) = stretches[i] {
+                ball.rod.length = thetas[index];
+                ball.rod.length_rate = theta_dots[index];
             }
+        }
+        if let (Some(cart), Some(x)) = (&mut self.cart, cart_coordinate) {
+            cart.position = thetas[x];
+            cart.velocity = theta_dots[x];
+        }
+    }
 
-            let mut theta_dots_half = theta_dots.clone();
-            for i in 0..self.balls.len() {
-                theta_dots_half[i] += theta_ddots[i] * (dt / 2.0);
-            }
+    // Pack the balls into the state vector layout an integrator works on
+    fn pack_state(&self, space: StateSpace) -> DVector<f64> {
+        let n = self.coordinate_count();
+        let (thetas, theta_dots) = self.coordinates();
+
+        let mut state = DVector::from_element(2 * n, 0.0);
+        state.rows_mut(0, n).copy_from(&thetas);
+        match space {
+            StateSpace::Velocities => state.rows_mut(n, n).copy_from(&theta_dots),
+            StateSpace::Momenta =>
+                state.rows_mut(n, n).copy_from(&(self.mass_matrix(&thetas) * &theta_dots)),
+        }
+        state
+    }
+
+    // Angular velocities of a state vector, None if momenta can't be converted back
+    fn unpacked_theta_dots(&self, space: StateSpace, state: &DVector<f64>) -> Option<DVector<f64>> {
+        let n = self.coordinate_count();
+        match space {
+            StateSpace::Velocities => Some(state.rows(n, n).into_owned()),
+            StateSpace::Momenta =>
+                LU::new(self.mass_matrix(&state.rows(0, n).into_owned())).solve(
+                    &state.rows(n, n).into_owned()
+                ),
+        }
+    }
+
+    // Write a state vector back into the balls. Fails if momenta can't be converted back
+    // to angular velocities.
+    fn unpack_state(&mut self, space: StateSpace, state: &DVector<f64>) -> bool {
+        let n = self.coordinate_count();
+        let thetas = state.rows(0, n).into_owned();
+        let Some(theta_dots) = self.unpacked_theta_dots(space, state) else {
+            return false;
+        };
 
-            // Step 2: Full-step position update using half-step velocity
-            let mut new_thetas = thetas.clone();
-            for i in 0..self.balls.len() {
-                new_thetas[i] += theta_dots_half[i] * dt;
+        self.set_coordinates(&thetas, &theta_dots);
+        true
+    }
+
+    // Time derivative of a packed state
+    fn derivative(&self, space: StateSpace, t: f64, state: &DVector<f64>) -> Option<DVector<f64>> {
+        match space {
+            StateSpace::Velocities => {
+                let n = self.coordinate_count();
+                let (theta_dots, theta_ddots) = self.calculate_accelerations(
+                    t,
+                    &state.rows(0, n).into_owned(),
+                    &state.rows(n, n).into_owned()
+                );
+                let mut derivative = DVector::from_element(2 * n, 0.0);
+                derivative.rows_mut(0, n).copy_from(&theta_dots);
+                derivative.rows_mut(n, n).copy_from(&theta_ddots);
+                Some(derivative)
             }
+            StateSpace::Momenta => self.canonical_derivative(t, state),
+        }
+    }
 
-            // Step 3: Calculate accelerations at new position
-            let (_, theta_ddots_new) = self.calculate_accelerations(&new_thetas, &theta_dots_half);
+    fn watching_events(&self) -> bool {
+        self.detect_bottom_passes || self.detect_link_flips || !self.event_functions.is_empty()
+    }
 
-            if theta_ddots_new.iter().any(|&x| x.is_nan()) {
-                return 1;
+    // Cubic Hermite interpolant of the step of dt from `before` at time t0 to the current
+    // state, built from both states and their derivatives, as a function of the fraction of
+    // the step gone. None if either derivative can't be evaluated.
+    fn step_interpolant(
+        &self,
+        t0: f64,
+        before: &DVector<f64>,
+        dt: f64
+    ) -> Option<impl Fn(f64) -> DVector<f64>> {
+        let mut after = self.pack_state(StateSpace::Velocities);
+        // Angles may have been wrapped by the integrator, interpolate the short way
+        for i in 0..self.balls.len() {
+            after[i] = before[i] + Self::normalize_angle(after[i] - before[i]);
+        }
+        let derivative_before = self.derivative(StateSpace::Velocities, t0, before)?;
+        let derivative_after = self.derivative(StateSpace::Velocities, t0 + dt, &after)?;
+        let before = before.clone();
+        Some(move |s: f64| -> DVector<f64> {
+            let (s2, s3) = (s * s, s * s * s);
+            &before * (2.0 * s3 - 3.0 * s2 + 1.0) +
+                &derivative_before * ((s3 - 2.0 * s2 + s) * dt) +
+                &after * (-2.0 * s3 + 3.0 * s2) +
+                &derivative_after * ((s3 - s2) * dt)
+        })
+    }
+
+    // Look for sign changes of the event functions over the step that just went from
+    // `before` to the current state. Crossings are located by bisection on the step's
+    // interpolant.
+    fn detect_events(&mut self, before: &DVector<f64>, dt: f64) {
+        let n = self.balls.len();
+        let size = self.coordinate_count();
+        let t0 = self.time;
+        let Some(interpolate) = self.step_interpolant(t0, before, dt) else {
+            return;
+        };
+        let evaluate = |kind: EventKind, index: usize, s: f64| -> f64 {
+            let state = interpolate(s);
+            match kind {
+                EventKind::BobBottom | EventKind::LinkFlip => f64::sin(state[index]),
+                EventKind::Custom => {
+                    let (_, function) = self.event_functions
+                        .iter()
+                        .find(|(id, _)| *id == index)
+                        .unwrap();
+                    function(t0 + s * dt, state.rows(0, n).as_slice(), state.rows(size, n).as_slice())
+                }
             }
+        };
 
-            // Step 4: Complete velocity update with second half-step
-            for i in 0..self.balls.len() {
-                self.balls[i].theta = new_thetas[i];
-                self.balls[i].omega = theta_dots_half[i] + theta_ddots_new[i] * (dt / 2.0);
+        let mut watched = vec![];
+        if self.detect_bottom_passes || self.detect_link_flips {
+            // Both are zeros of sin(theta), told apart by which way the link points
+            for i in 0..n {
+                watched.push((EventKind::BobBottom, i));
+            }
+        }
+        for (id, _) in &self.event_functions {
+            watched.push((EventKind::Custom, *id));
+        }
 
-                // Normalize angle to [-PI, PI] for better floating point precision
-                self.balls[i].theta = Self::normalize_angle(self.balls[i].theta);
+        let mut found = vec![];
+        for (kind, index) in watched {
+            let g0 = evaluate(kind, index, 0.0);
+            let g1 = evaluate(kind, index, 1.0);
+            if !(g0 != 0.0 && g0 * g1 <= 0.0) {
+                continue;
+            }
 
-                // Calculate positions (cumulative from origin)
-                let mut x = 0.0;
-                let mut y = 0.0;
-                for j in 0..=i {
-                    x += self.balls[j].rod.length * f64::sin(self.balls[j].theta);
-                    y += self.balls[j].rod.length * f64::cos(self.balls[j].theta);
+            let (mut low, mut high) = (0.0, 1.0);
+            for _ in 0..60 {
+                let middle = 0.5 * (low + high);
+                if evaluate(kind, index, middle) * g0 > 0.0 {
+                    low = middle;
+                } else {
+                    high = middle;
                 }
-                self.balls[i].pos.x = x;
-                self.balls[i].pos.y = y;
+            }
+            let s = 0.5 * (low + high);
+
+            let kind = match kind {
+                EventKind::Custom => EventKind::Custom,
+                _ if f64::cos(interpolate(s)[index]) > 0.0 => EventKind::BobBottom,
+                _ => EventKind::LinkFlip,
+            };
+            let wanted = match kind {
+                EventKind::BobBottom => self.detect_bottom_passes,
+                EventKind::LinkFlip => self.detect_link_flips,
+                EventKind::Custom => true,
+            };
+            if wanted {
+                found.push(Event {
+                    kind,
+                    index,
+                    time: t0 + s * dt,
+                    direction: if g1 > g0 { 1 } else { -1 },
+                });
             }
         }
+        found.sort_by(|a, b| (a.time * dt.signum()).total_cmp(&(b.time * dt.signum())));
+        self.events.extend(found);
+    }
 
-        // Apply energy conservation constraint to prevent unbounded energy growth (if enabled)
-        if self.limit_total_energy {
-            self.constrain_velocities(self.initial_energy);
+    // Step by dt. A joint running into one of its angle stops cuts the step short at the
+    // impact: the step is taken again up to there, the chain bounces off the stop and the
+    // rest of the step goes on from there. Joints that start the step on a stop, resting
+    // against it, are put back on it at the end.
+    fn single_physics_step(&mut self, dt: f64) -> u8 {
+        const MAX_IMPACTS: usize = 16;
+        if !self.has_angle_limits() {
+            return self.integrate_step(dt);
+        }
+        let mut remaining = dt;
+        for impacts in 0.. {
+            let checkpoint = self.checkpoint();
+            let result = self.integrate_step(remaining);
+            if result != 0 {
+                return result;
+            }
+            // Past too many impacts, the step stands and the stops are enforced after it
+            if impacts == MAX_IMPACTS {
+                break;
+            }
+            let Some(impact) = self.limit_crossing(&checkpoint, remaining) else {
+                break;
+            };
+            let Some(s) = self.step_to_stop(&checkpoint, remaining, &impact) else {
+                return 1;
+            };
+            // The interpolant can see an impact the integrator doesn't, then only the part
+            // of the step up to there is taken
+            let (thetas, _) = self.coordinates();
+            let clearance = self.limit_clearances(thetas.as_slice(), impact.index)[impact.stop];
+            if clearance < LIMIT_TOLERANCE {
+                self.interrupt_shadow_runs();
+                self.resolve_limit(impact.index);
+                self.restart_integrator();
+            }
+            remaining *= 1.0 - s;
         }
 
-        return 0;
+        let (thetas, _) = self.coordinates();
+        let past: Vec<usize> = (0..self.balls.len())
+            .filter(|&i| self.limit_clearances(thetas.as_slice(), i).iter().any(|&g| g < 0.0))
+            .collect();
+        if !past.is_empty() {
+            self.interrupt_shadow_runs();
+            for index in past {
+                self.resolve_limit(index);
+            }
+            self.restart_integrator();
+        }
+        0
     }
-    fn calculate_accelerations(
-        &self,
-        thetas: &DVector<f64>,
-        theta_dots: &DVector<f64>
-    ) -> (DVector<f64>, DVector<f64>) {
-        let n = self.balls.len();
 
-        if self.mass_calculation {
-            // Extract masses and lengths
-            let masses: Vec<f64> = self.balls
-                .iter()
-                .map(|ball| ball.mass)
-                .collect();
-            let lengths: Vec<f64> = self.balls
-                .iter()
-                .map(|ball| ball.rod.length)
-                .collect();
+    fn has_angle_limits(&self) -> bool {
+        self.balls.iter().any(|ball| ball.min_angle.is_finite() || ball.max_angle.is_finite())
+    }
 
-            // Build the mass matrix M
-            let mut m: DMatrix<f64> = DMatrix::from_element(n, n, 0.0);
-            for i in 0..n {
-                for j in 0..n {
-                    // Sum of masses from max(i,j) to n-1
-                    let mass_sum: f64 = (usize::max(i, j)..n).map(|k| masses[k]).sum();
+    // Angle of joint i, between link i and the link above it (straight down for the top
+    // joint of a chain), in [-PI, PI]
+    fn joint_angle(&self, thetas: &[f64], index: usize) -> f64 {
+        let above = self.parent(index).map_or(0.0, |parent| thetas[parent]);
+        Self::normalize_angle(thetas[index] - above)
+    }
 
-                    m[(i, j)] =
-                        mass_sum * lengths[i] * lengths[j] * f64::cos(thetas[i] - thetas[j]);
+    // How far joint i is from its lower and its upper stop, negative once past one
+    fn limit_clearances(&self, thetas: &[f64], index: usize) -> [f64; 2] {
+        let ball = &self.balls[index];
+        let angle = self.joint_angle(thetas, index);
+        [angle - ball.min_angle, ball.max_angle - angle]
+    }
+
+    // The earliest impact on a stop over the step of dt taken from the checkpoint, located on
+    // the step's interpolant. The clearance is sampled along the step so that a joint going
+    // past a stop and back within the step, or bouncing off a stop and running into it again,
+    // is caught too. A joint resting on a stop never gets clear of it and is left to the
+    // check after the step.
+    fn limit_crossing(&self, checkpoint: &Checkpoint, dt: f64) -> Option<Impact> {
+        const SAMPLES: usize = 16;
+        let interpolate = self.step_interpolant(checkpoint.time, &checkpoint.state, dt)?;
+        let clearance = |s: f64, index: usize, stop: usize| -> f64 {
+            self.limit_clearances(interpolate(s).as_slice(), index)[stop]
+        };
+        let mut earliest: Option<Impact> = None;
+        for index in 0..self.balls.len() {
+            let start = self.limit_clearances(checkpoint.state.as_slice(), index);
+            for (stop, &clearance_before) in start.iter().enumerate() {
+                // No stop on this side
+                if !clearance_before.is_finite() {
+                    continue;
+                }
+                let mut low = (0.0, clearance_before);
+                for k in 1..=SAMPLES {
+                    // Nothing further on can come before an impact already found
+                    if earliest.as_ref().is_some_and(|first| low.0 > first.guess) {
+                        break;
+                    }
+                    let s = (k as f64) / (SAMPLES as f64);
+                    let high = (s, clearance(s, index, stop));
+                    if !(low.1 > LIMIT_TOLERANCE && high.1 < 0.0) {
+                        low = high;
+                        continue;
+                    }
+                    let (mut before, mut after) = (low.0, high.0);
+                    for _ in 0..60 {
+                        let middle = 0.5 * (before + after);
+                        if clearance(middle, index, stop) > 0.0 {
+                            before = middle;
+                        } else {
+                            after = middle;
+                        }
+                    }
+                    let guess = 0.5 * (before + after);
+                    if earliest.as_ref().is_none_or(|first| guess < first.guess) {
+                        earliest = Some(Impact { index, stop, past: high.0, guess });
+                    }
+                    break;
                 }
             }
+        }
+        earliest
+    }
 
-            let mut v = DVector::from_element(n, 0.0);
-            for i in 0..n {
-                let mut sum = 0.0;
-
-                for j in 0..n {
-                    let mass_sum: f64 = (usize::max(i, j)..n).map(|k| masses[k]).sum();
-
-                    sum -=
-                        mass_sum *
-                        lengths[i] *
-                        lengths[j] *
-                        f64::sin(thetas[i] - thetas[j]) *
-                        f64::powi(theta_dots[j], 2);
+    // Take the step from the checkpoint again, up to the impact. The interpolant only gives
+    // a first guess, the impact is bracketed and closed in on by the clearance the integrator
+    // actually ends with, by regula falsi (Illinois) once the joint has been seen clear of
+    // the stop and by bisection before. Returns the fraction of the step taken, None if the
+    // integrator failed.
+    fn step_to_stop(&mut self, checkpoint: &Checkpoint, dt: f64, impact: &Impact) -> Option<f64> {
+        const MAX_REFINEMENTS: usize = 12;
+        let Impact { index, stop, past, guess } = *impact;
+        let start = self.limit_clearances(checkpoint.state.as_slice(), index)[stop];
+        let mut low = (0.0, start); // Last fraction known to be short of the impact
+        let mut high: Option<(f64, f64)> = None; // First one known to be past it
+        let mut kept = 0; // Which end was kept last time, +1 high and -1 low
+        let mut s = guess;
+        for refinement in 0.. {
+            self.restore(checkpoint);
+            if self.integrate_step(s * dt) != 0 {
+                return None;
+            }
+            let (thetas, _) = self.coordinates();
+            let g = self.limit_clearances(thetas.as_slice(), index)[stop];
+            if g.abs() < LIMIT_TOLERANCE || refinement == MAX_REFINEMENTS {
+                break;
+            }
+            if g > 0.0 {
+                low = (s, g);
+                if kept > 0 {
+                    if let Some(high) = &mut high {
+                        high.1 *= 0.5;
+                    }
+                }
+                kept = 1;
+            } else {
+                high = Some((s, g));
+                if kept < 0 {
+                    low.1 *= 0.5;
                 }
+                kept = -1;
+            }
+            s = match high {
+                // Not past the stop yet where the interpolant is, try further on
+                None if s < past => past,
+                None => 0.5 * (s + 1.0),
+                Some(high) if low.1 > LIMIT_TOLERANCE => {
+                    low.0 + ((high.0 - low.0) * low.1) / (low.1 - high.1)
+                }
+                Some(high) => 0.5 * (low.0 + high.0),
+            };
+        }
+        Some(s)
+    }
 
-                // Gravitational term
-                let mass_sum_i: f64 = (i..n).map(|k| masses[k]).sum();
-                sum -= self.gravity * mass_sum_i * lengths[i] * f64::sin(thetas[i]);
+    // Put joint i back on the stop it went past, turning its link and everything below, and
+    // bounce it off if it's still moving into the stop. The impulse acts along the joint's
+    // rotation J (+1 on link i, -1 on the link above) and is spread over the whole chain by
+    // the mass matrix,
+    //   q' += -(1 + e) (J q') / (J M^-1 J^T) M^-1 J^T
+    // which turns the joint's rate J q' into -e J q' and leaves the momentum of every motion
+    // that doesn't move the joint alone. The energy lost is counted as dissipated.
+    fn resolve_limit(&mut self, index: usize) {
+        let energy = self.get_total_energy();
+        let (mut thetas, mut theta_dots) = self.coordinates();
+        let ball = &self.balls[index];
+        let angle = self.joint_angle(thetas.as_slice(), index);
+        let (limit, outward) = if angle - ball.min_angle < ball.max_angle - angle {
+            (ball.min_angle, -1.0)
+        } else {
+            (ball.max_angle, 1.0)
+        };
+        let restitution = ball.restitution;
+        let parents = self.parents();
+        for i in index..self.balls.len() {
+            if Self::hangs_from(&parents, i, index) {
+                thetas[i] += limit - angle;
+            }
+        }
 
-                v[i] = sum;
+        let mut joint = DVector::zeros(thetas.len());
+        joint[index] = 1.0;
+        if let Some(parent) = parents[index] {
+            joint[parent] = -1.0;
+        }
+        let rate = joint.dot(&theta_dots);
+        if rate * outward > 0.0 {
+            if let Some(response) = LU::new(self.mass_matrix(&thetas)).solve(&joint) {
+                theta_dots -= &response * ((1.0 + restitution) * rate / joint.dot(&response));
             }
+        }
+        self.set_coordinates(&thetas, &theta_dots);
+        self.update_positions();
+        self.dissipated_energy += energy - self.get_total_energy();
+    }
 
-            let lu = LU::new(m);
-            let theta_ddots = lu.solve(&v).unwrap_or_else(|| DVector::from_element(n, 0.0));
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            state: self.pack_state(StateSpace::Velocities),
+            time: self.time,
+            dissipated_energy: self.dissipated_energy,
+            pivot_work: self.pivot_work,
+            actuator_work: self.actuator_work,
+            field_work: self.field_work,
+            last_energy_correction: self.last_energy_correction,
+            total_energy_correction: self.total_energy_correction,
+            events: self.events.len(),
+        }
+    }
 
-            (theta_dots.clone(), theta_ddots)
-        } else {
-            // Use default mass for all balls when mass_calculation is false
-            let masses: Vec<f64> = vec![self.default_mass; n];
-            let lengths: Vec<f64> = self.balls
-                .iter()
-                .map(|ball| ball.rod.length)
-                .collect();
+    // Go back to a checkpoint taken earlier in the same step
+    fn restore(&mut self, checkpoint: &Checkpoint) {
+        self.unpack_state(StateSpace::Velocities, &checkpoint.state);
+        self.time = checkpoint.time;
+        self.dissipated_energy = checkpoint.dissipated_energy;
+        self.pivot_work = checkpoint.pivot_work;
+        self.actuator_work = checkpoint.actuator_work;
+        self.field_work = checkpoint.field_work;
+        self.last_energy_correction = checkpoint.last_energy_correction;
+        self.total_energy_correction = checkpoint.total_energy_correction;
+        self.events.truncate(checkpoint.events);
+        self.update_positions();
+        self.restart_integrator();
+    }
 
-            // Build the mass matrix M (same as mass_calculation=true, but with default_mass)
-            let mut m: DMatrix<f64> = DMatrix::from_element(n, n, 0.0);
-            for i in 0..n {
-                for j in 0..n {
-                    // Sum of masses from max(i,j) to n-1
-                    let mass_sum: f64 = (usize::max(i, j)..n).map(|k| masses[k]).sum();
+    fn integrate_step(&mut self, dt: f64) -> u8 {
+        // Take the integrator out so it can read the rest of the universe while stepping
+        let mut integrator = self.integrator.take().unwrap_or_else(|| self.build_integrator());
+        let space = integrator.state_space();
+        let state = self.pack_state(space);
+        let before = self.watching_events().then(|| self.pack_state(StateSpace::Velocities));
+        let dynamics = ChainDynamics { universe: self, space, flow: Cell::new(None) };
+        let new_state = integrator.step_dynamics(&dynamics, self.time, &state, dt);
+        // Adaptive integrators report their internal steps, the others take just this one
+        if let (None, Some(new_state)) = (dynamics.flow.get(), &new_state) {
+            dynamics.accepted_step(self.time, &state, new_state, dt);
+        }
+        let flow = dynamics.flow.get().unwrap_or_default();
+        self.integrator = Some(integrator);
 
-                    m[(i, j)] =
-                        mass_sum * lengths[i] * lengths[j] * f64::cos(thetas[i] - thetas[j]);
-                }
+        // Check for NaN before updating
+        let Some(new_state) = new_state else {
+            return 1;
+        };
+        if new_state.iter().any(|x| !x.is_finite()) || !self.unpack_state(space, &new_state) {
+            return 1;
+        }
+        if let Some(before) = before {
+            self.detect_events(&before, dt);
+        }
+        self.time += dt;
+        self.dissipated_energy += flow.dissipated;
+        self.pivot_work += flow.pivot;
+        self.actuator_work += flow.actuated;
+        self.field_work += flow.field;
+
+        if
+            !self.custom_integrator &&
+            matches!(
+                self.implementation,
+                Implementation::Verlet | Implementation::Leapfrog | Implementation::SymmetricLeapfrog
+            )
+        {
+            // Normalize angle to [-PI, PI] for better floating point precision
+            for ball in &mut self.balls {
+                ball.theta = Self::normalize_angle(ball.theta);
             }
+        }
+        self.update_positions();
 
-            // Build the force vector
-            let mut v = DVector::from_element(n, 0.0);
-            for i in 0..n {
-                let mut sum = 0.0;
+        // Apply energy conservation constraint to prevent unbounded energy growth (if enabled)
+        if self.limit_total_energy {
+            self.limit_energy();
+        }
 
-                for j in 0..n {
-                    let mass_sum: f64 = (usize::max(i, j)..n).map(|k| masses[k]).sum();
+        0
+    }
+
+    // Entry k is the mass of ball k and everything hanging below it
+    fn masses_below(&self) -> Vec<f64> {
+        let parents = self.parents();
+        // Use default mass for all balls when mass_calculation is false
+        let mut below: Vec<f64> = self.balls
+            .iter()
+            .map(|ball| if self.mass_calculation { ball.mass } else { self.default_mass })
+            .collect();
+        for k in (0..below.len()).rev() {
+            if let Some(parent) = parents[k] {
+                below[parent] += below[k];
+            }
+        }
+        below
+    }
 
-                    sum -=
-                        mass_sum *
-                        lengths[i] *
-                        lengths[j] *
-                        f64::sin(thetas[i] - thetas[j]) *
-                        f64::powi(theta_dots[j], 2);
+    // How the mass of the chain is spread along it, see MassDistribution
+    fn mass_distribution(&self) -> MassDistribution {
+        let n = self.balls.len();
+        let parents = self.parents();
+        let below = self.masses_below();
+        let mut rods_below = vec![0.0; n]; // Rod masses strictly below link k
+        if self.rod_inertia {
+            for k in (0..n).rev() {
+                if let Some(parent) = parents[k] {
+                    rods_below[parent] += rods_below[k] + self.balls[k].rod.mass;
                 }
+            }
+        }
 
-                // Gravitational term
-                let mass_sum_i: f64 = (i..n).map(|k| masses[k]).sum();
-                sum -= self.gravity * mass_sum_i * lengths[i] * f64::sin(thetas[i]);
+        // Links on different branches or chains share no masses. If one hangs from the
+        // other, the masses both links move are the ones below the lower link k.
+        let mut coupling = vec![0.0; n * n];
+        for k in 0..n {
+            let mut shared = below[k];
+            if self.rod_inertia {
+                // A uniform rod is a mass at the middle of its link (c = 1/2 there) spinning
+                // with the link, with moment of inertia m * l^2 / 12 about its centre
+                shared += rods_below[k] + 0.5 * self.balls[k].rod.mass;
+                coupling[k * n + k] = below[k] + rods_below[k] + 0.25 * self.balls[k].rod.mass;
+            } else {
+                coupling[k * n + k] = shared;
+            }
+            let mut above = parents[k];
+            while let Some(i) = above {
+                coupling[i * n + k] = shared;
+                coupling[k * n + i] = shared;
+                above = parents[i];
+            }
+        }
 
-                v[i] = sum;
+        let mut total = 0.0;
+        let mut moment = below;
+        let mut spin = vec![0.0; n];
+        for i in 0..n {
+            if parents[i].is_none() {
+                total += moment[i];
+            }
+            if self.rod_inertia {
+                let rod = &self.balls[i].rod;
+                moment[i] += rods_below[i] + 0.5 * rod.mass;
+                spin[i] = rod.mass / 12.0;
+                total += rod.mass;
             }
+        }
+        MassDistribution { coupling, moment, spin, total }
+    }
 
-            // Solve M * theta_ddot = v for theta_ddot
-            let lu = LU::new(m);
-            let theta_ddots = lu.solve(&v).unwrap_or_else(|| DVector::from_element(n, 0.0));
+    // Entries of the mass matrix M of M * theta_ddot = v, row by row. Generic over the number
+    // type so the model can also be differentiated and evaluated in other arithmetics.
+    // Link j moves its masses by l_j * omega_j across the rod and, if elastic, by l_j' along
+    // it, so with the unit vectors e_j = (sin(theta_j), cos(theta_j)) along the rod and
+    // n_j = (cos(theta_j), -sin(theta_j)) across it, each entry is the coupling times the dot
+    // product of the two directions:
+    //   M_(theta_i, theta_j) = coupling_ij * l_i * l_j * cos(theta_i - theta_j)
+    //   M_(theta_i, l_j)     = -coupling_ij * l_i * sin(theta_i - theta_j)
+    //   M_(l_i, l_j)         = coupling_ij * cos(theta_i - theta_j)
+    fn mass_matrix_entries<T: Scalar>(&self, thetas: &[T]) -> Vec<T> {
+        let n = self.balls.len();
+        let size = self.coordinate_count();
+        let distribution = self.mass_distribution();
+        let stretches = self.stretch_coordinates();
+        let lengths = self.link_lengths(thetas);
+
+        let mut m = vec![T::constant(0.0); size * size];
+        for i in 0..n {
+            for j in 0..n {
+                let coupling = T::constant(distribution.coupling[i * n + j]);
+                let (sin, cos) = (thetas[i].clone() - thetas[j].clone()).sin_cos();
+
+                m[i * size + j] =
+                    coupling.clone() * lengths[i].clone() * lengths[j].clone() * cos.clone();
+                if let Some(rj) = stretches[j] {
+                    m[i * size + rj] = -(coupling.clone() * lengths[i].clone() * sin.clone());
+                }
+                if let Some(ri) = stretches[i] {
+                    m[ri * size + j] = coupling.clone() * lengths[j].clone() * sin;
+                    if let Some(rj) = stretches[j] {
+                        m[ri * size + rj] = coupling * cos;
+                    }
+                }
+            }
 
-            (theta_dots.clone(), theta_ddots)
+            // The spin of a rod about its centre, and its stretch about the centre
+            if distribution.spin[i] != 0.0 {
+                let spin = T::constant(distribution.spin[i]);
+                m[i * size + i] =
+                    m[i * size + i].clone() + spin.clone() * lengths[i].clone() * lengths[i].clone();
+                if let Some(ri) = stretches[i] {
+                    m[ri * size + ri] = m[ri * size + ri].clone() + spin;
+                }
+            }
         }
+
+        if let (Some(cart), Some(x)) = (&self.cart, self.cart_coordinate()) {
+            // Sliding the cart carries every mass sideways, so it couples to each link like a
+            // horizontal link of its own
+            for j in 0..n {
+                let moment = T::constant(distribution.moment[j]);
+                let (sin, cos) = thetas[j].sin_cos();
+                m[x * size + j] = moment.clone() * lengths[j].clone() * cos;
+                m[j * size + x] = m[x * size + j].clone();
+                if let Some(rj) = stretches[j] {
+                    m[x * size + rj] = moment * sin;
+                    m[rj * size + x] = m[x * size + rj].clone();
+                }
+            }
+            m[x * size + x] = T::constant(cart.mass + distribution.total);
+        }
+        m
+    }
+
+    // Entries of the force vector v of M * theta_ddot = v. Besides the applied forces, v holds
+    // what the velocities alone do to the masses: link j accelerates the ones below it by
+    //   a_j = 2 * l_j' * omega_j * n_j - l_j * omega_j^2 * e_j
+    // (Coriolis and centripetal), which every coordinate feels through its direction.
+    fn force_entries<T: Scalar>(&self, t: &T, thetas: &[T], theta_dots: &[T]) -> Vec<T> {
+        let n = self.balls.len();
+        let distribution = self.mass_distribution();
+        let stretches = self.stretch_coordinates();
+        let lengths = self.link_lengths(thetas);
+        let rates = self.link_length_rates(theta_dots);
+        let squared: Vec<T> = theta_dots[..n]
+            .iter()
+            .map(|omega| omega.clone() * omega.clone())
+            .collect();
+        // 2 * l_j' * omega_j, None for a rigid rod
+        let coriolis: Vec<Option<T>> = (0..n)
+            .map(|j| {
+                stretches[j].map(|_| {
+                    T::constant(2.0) * rates[j].clone() * theta_dots[j].clone()
+                })
+            })
+            .collect();
+
+        let mut v = vec![T::constant(0.0); self.coordinate_count()];
+        for i in 0..n {
+            let li = lengths[i].clone();
+            let mut sum = T::constant(0.0);
+            let mut radial = T::constant(0.0);
+
+            for j in 0..n {
+                let coupling = T::constant(distribution.coupling[i * n + j]);
+                let (sin, cos) = (thetas[i].clone() - thetas[j].clone()).sin_cos();
+                let centripetal = lengths[j].clone() * squared[j].clone();
+
+                sum = sum - coupling.clone() * li.clone() * sin.clone() * centripetal.clone();
+                if let Some(coriolis) = &coriolis[j] {
+                    sum = sum - coupling.clone() * li.clone() * cos.clone() * coriolis.clone();
+                }
+                if stretches[i].is_some() {
+                    radial = radial + coupling.clone() * cos * centripetal;
+                    if let Some(coriolis) = &coriolis[j] {
+                        radial = radial - coupling * sin * coriolis.clone();
+                    }
+                }
+            }
+
+            let spin = T::constant(distribution.spin[i]);
+            if let Some(ri) = stretches[i] {
+                // A stretching rod speeds up its own spin, and the spin pulls it longer
+                let rod = &self.balls[i].rod;
+                sum = sum - spin.clone() * li.clone() * coriolis[i].clone().unwrap();
+                radial = radial + spin * li.clone() * squared[i].clone();
+
+                // The spring
+                radial =
+                    radial -
+                    T::constant(rod.stiffness) * (li - T::constant(rod.rest_length)) -
+                    T::constant(rod.damping) * rates[i].clone();
+                v[ri] = radial;
+            }
+            v[i] = sum;
+        }
+
+        if let (Some(cart), Some(x)) = (&self.cart, self.cart_coordinate()) {
+            // Centripetal and Coriolis pull of the swinging links on the cart, the rail friction
+            // and the applied force
+            let velocity = theta_dots[x].clone();
+            let mut sum = T::constant(cart.force) - T::constant(cart.friction) * velocity;
+            for j in 0..n {
+                let moment = T::constant(distribution.moment[j]);
+                let (sin, cos) = thetas[j].sin_cos();
+                sum = sum + moment.clone() * lengths[j].clone() * squared[j].clone() * sin;
+                if let Some(coriolis) = &coriolis[j] {
+                    sum = sum - moment * coriolis.clone() * cos;
+                }
+            }
+            v[x] = sum;
+        }
+
+        // Joint friction and motors act on the link below the joint and, reversed, on the
+        // link above
+        for i in 0..n {
+            let torques = [self.friction_torque(i, theta_dots), self.joint_torque(i, t)];
+            for torque in torques.into_iter().flatten() {
+                v[i] = v[i].clone() + torque.clone();
+                if let Some(parent) = self.parent(i) {
+                    v[parent] = v[parent].clone() - torque;
+                }
+            }
+        }
+
+        let external = [
+            Some(self.gravity_forces(t, thetas)),
+            self.drag_forces(t, thetas, theta_dots).map(|(q, _)| q),
+            self.pivot_forces(t, thetas),
+        ];
+        for forces in external.into_iter().flatten() {
+            for (vi, qi) in v.iter_mut().zip(forces) {
+                *vi = vi.clone() + qi;
+            }
+        }
+        v
+    }
+
+    // Coordinate of each link's length, None for a rigid rod. The lengths of the elastic
+    // rods follow the angles in the coordinates, in link order.
+    fn stretch_coordinates(&self) -> Vec<Option<usize>> {
+        let mut next = self.balls.len();
+        self.balls
+            .iter()
+            .map(|ball| {
+                ball.rod.is_elastic().then(|| {
+                    next += 1;
+                    next - 1
+                })
+            })
+            .collect()
+    }
+
+    // The cart position comes last
+    fn cart_coordinate(&self) -> Option<usize> {
+        self.cart.map(|_| self.coordinate_count() - 1)
+    }
+
+    // Link lengths at the coordinates, fixed for rigid rods
+    fn link_lengths<T: Scalar>(&self, thetas: &[T]) -> Vec<T> {
+        self.stretch_coordinates()
+            .iter()
+            .zip(&self.balls)
+            .map(|(stretch, ball)| match stretch {
+                Some(index) => thetas[*index].clone(),
+                None => T::constant(ball.rod.length),
+            })
+            .collect()
+    }
+
+    // Rates of change of the link lengths, zero for rigid rods
+    fn link_length_rates<T: Scalar>(&self, theta_dots: &[T]) -> Vec<T> {
+        self.stretch_coordinates()
+            .iter()
+            .map(|stretch| match stretch {
+                Some(index) => theta_dots[*index].clone(),
+                None => T::constant(0.0),
+            })
+            .collect()
+    }
+
+    // Rotation rate of joint i, link i relative to the link above (or the fixed pivot)
+    fn joint_velocity<T: Scalar>(&self, index: usize, theta_dots: &[T]) -> T {
+        match self.parent(index) {
+            Some(parent) => theta_dots[index].clone() - theta_dots[parent].clone(),
+            None => theta_dots[index].clone(),
+        }
+    }
+
+    // Friction torque of joint i on the link below it, None without friction. Coulomb
+    // friction is regularised as -T_c * w / sqrt(w^2 + w_s^2): full torque while slipping,
+    // and like a stiff viscous damper that holds the joint still below the stick velocity w_s.
+    fn friction_torque<T: Scalar>(&self, index: usize, theta_dots: &[T]) -> Option<T> {
+        let ball = &self.balls[index];
+        if ball.viscous_friction == 0.0 && ball.coulomb_friction == 0.0 {
+            return None;
+        }
+        let w = self.joint_velocity(index, theta_dots);
+        let mut torque = -(T::constant(ball.viscous_friction) * w.clone());
+        if ball.coulomb_friction != 0.0 {
+            let stick = T::constant(self.stick_velocity * self.stick_velocity);
+            let slip = (w.clone() * w.clone() + stick).sqrt();
+            torque = torque - (T::constant(ball.coulomb_friction) * w) / slip;
+        }
+        Some(torque)
+    }
+
+    // Motor torque of joint i at time t, None if the joint isn't driven. A schedule is
+    // interpolated linearly between its points and held beyond them; the Taylor integrator
+    // and the enclosure expand it within the segment containing the step's start.
+    fn joint_torque<T: Scalar>(&self, index: usize, t: &T) -> Option<T> {
+        let ball = &self.balls[index];
+        let schedule = &ball.torque_schedule;
+        let (Some(&(_, first)), Some(&(_, last))) = (schedule.first(), schedule.last()) else {
+            return (ball.torque != 0.0).then(|| T::constant(ball.torque));
+        };
+        let k = schedule.partition_point(|&(time, _)| time <= t.value());
+        if k == 0 {
+            return Some(T::constant(first));
+        }
+        if k == schedule.len() {
+            return Some(T::constant(last));
+        }
+        let ((t0, y0), (t1, y1)) = (schedule[k - 1], schedule[k]);
+        Some(T::constant(y0) + T::constant((y1 - y0) / (t1 - t0)) * (t.clone() - T::constant(t0)))
+    }
+
+    // Generalized forces of the drag on the bobs and the total drag force, None in vacuum. A
+    // sphere of radius r moving at velocity u through still air feels -(b1 + b2 * |u|) * u,
+    // with Stokes drag b1 = 6 * pi * mu * r from the viscosity and b2 = 1/2 * rho * C_d * pi *
+    // r^2 from the density. Bob k moves at
+    //   u_k = v + sum_{j<=k} l_j * omega_j * n_j + l_j' * e_j
+    // with v the velocity of the cart or the moving pivot (see mass_matrix_entries for n_j and
+    // e_j), so its drag F_k contributes Q_i = l_i * F_k . n_i to every link i <= k, F_k . e_i
+    // to the length of an elastic one, and F_kx to the cart.
+    fn drag_forces<T: Scalar>(
+        &self,
+        t: &T,
+        thetas: &[T],
+        theta_dots: &[T]
+    ) -> Option<(Vec<T>, (T, T))> {
+        if !self.has_drag() {
+            return None;
+        }
+        let n = self.balls.len();
+        // |u| isn't smooth at rest, where the first bob comes at every turn of the top link.
+        // It is rounded off below a speed far under anything visible, which keeps the series
+        // of Taylor steps through a turn well conditioned.
+        let chain_length: f64 = self.balls.iter().map(|ball| ball.rod.length).sum();
+        let floor = T::constant(f64::powi(1e-3 * chain_length, 2));
+        let stretches = self.stretch_coordinates();
+        let lengths = self.link_lengths(thetas);
+        let rates = self.link_length_rates(theta_dots);
+        let cart = self.cart_coordinate();
+        let parents = self.parents();
+        let trig: Vec<(T, T)> = thetas[..n].iter().map(|theta| theta.sin_cos()).collect();
+        let mut q = vec![T::constant(0.0); self.coordinate_count()];
+        let zero = || T::constant(0.0);
+        let support = match cart {
+            Some(x) => (theta_dots[x].clone(), zero()),
+            None => self.pivot_velocity(t).unwrap_or_else(|| (zero(), zero())),
+        };
+        let mut total = (zero(), zero());
+        let mut velocities: Vec<(T, T)> = Vec::with_capacity(n);
+        for k in 0..n {
+            let (mut ux, mut uy) = match parents[k] {
+                Some(parent) => velocities[parent].clone(),
+                None => support.clone(),
+            };
+            let (sin, cos) = trig[k].clone();
+            let rate = lengths[k].clone() * theta_dots[k].clone();
+            ux = ux + rate.clone() * cos.clone();
+            uy = uy - rate * sin.clone();
+            if stretches[k].is_some() {
+                ux = ux + rates[k].clone() * sin;
+                uy = uy + rates[k].clone() * cos;
+            }
+            velocities.push((ux.clone(), uy.clone()));
+
+            let radius = self.balls[k].radius.max(0) as f64;
+            let linear = 6.0 * PI * self.medium_viscosity * radius;
+            let quadratic = 0.5 * self.medium_density * self.drag_coefficient * PI * radius * radius;
+            let speed = (ux.clone() * ux.clone() + uy.clone() * uy.clone() + floor.clone()).sqrt();
+            let resistance = T::constant(linear) + T::constant(quadratic) * speed;
+            let (fx, fy) = (-(resistance.clone() * ux.clone()), -(resistance * uy.clone()));
+            total = (total.0 + fx.clone(), total.1 + fy.clone());
+
+            // The ball moves with every link it hangs from
+            let mut link = Some(k);
+            while let Some(i) = link {
+                let (sin, cos) = trig[i].clone();
+                let projected = fx.clone() * cos.clone() - fy.clone() * sin.clone();
+                q[i] = q[i].clone() + lengths[i].clone() * projected;
+                if let Some(ri) = stretches[i] {
+                    q[ri] = q[ri].clone() + fx.clone() * sin + fy.clone() * cos;
+                }
+                link = parents[i];
+            }
+            if let Some(x) = cart {
+                q[x] = q[x].clone() + fx;
+            }
+        }
+        Some((q, total))
+    }
+
+    fn has_drag(&self) -> bool {
+        self.medium_density != 0.0 || self.medium_viscosity != 0.0
+    }
+
+    // Acceleration of the pivot at time t, None while it stands still or rides on the cart
+    // (which is a coordinate, not a prescribed motion)
+    fn pivot_acceleration<T: Scalar>(&self, t: &T) -> Option<(T, T)> {
+        if self.cart.is_some() {
+            return None;
+        }
+        let (a, w) = (self.pivot_amplitude, self.pivot_frequency);
+        let phase = T::constant(w) * t.clone();
+        let scale = T::constant(-a * w * w);
+        match self.pivot_motion {
+            PivotMotion::Fixed => None,
+            PivotMotion::Vertical => Some((T::constant(0.0), scale * phase.sin())),
+            PivotMotion::Horizontal => Some((scale * phase.sin(), T::constant(0.0))),
+            PivotMotion::Circular => {
+                let (sin, cos) = phase.sin_cos();
+                Some((scale.clone() * cos, scale * sin))
+            }
+            // Opaque to the arithmetic, so constant over a series step
+            PivotMotion::Custom => {
+                let (_, _, acceleration) = (self.pivot_function.as_ref()?)(t.value());
+                Some((T::constant(acceleration.x), T::constant(acceleration.y)))
+            }
+        }
+    }
+
+    // Velocity of the pivot at time t, None while it stands still or rides on the cart
+    fn pivot_velocity<T: Scalar>(&self, t: &T) -> Option<(T, T)> {
+        if self.cart.is_some() {
+            return None;
+        }
+        let (a, w) = (self.pivot_amplitude, self.pivot_frequency);
+        let phase = T::constant(w) * t.clone();
+        let scale = T::constant(a * w);
+        match self.pivot_motion {
+            PivotMotion::Fixed => None,
+            PivotMotion::Vertical => Some((T::constant(0.0), scale * phase.cos())),
+            PivotMotion::Horizontal => Some((scale * phase.cos(), T::constant(0.0))),
+            PivotMotion::Circular => {
+                let (sin, cos) = phase.sin_cos();
+                Some((-(scale.clone() * sin), scale * cos))
+            }
+            // Opaque to the arithmetic, so constant over a series step
+            PivotMotion::Custom => {
+                let (_, velocity, _) = (self.pivot_function.as_ref()?)(t.value());
+                Some((T::constant(velocity.x), T::constant(velocity.y)))
+            }
+        }
+    }
+
+    fn pivot_position(&self, t: f64) -> Vec2 {
+        if let Some(cart) = &self.cart {
+            return Vec2::new(cart.position, 0.0);
+        }
+        let (x, y) = self.pivot_offset(&t);
+        Vec2::new(x, y)
+    }
+
+    // Where the prescribed motion has taken the pivot at time t, in any arithmetic
+    fn pivot_offset<T: Scalar>(&self, t: &T) -> (T, T) {
+        let a = T::constant(self.pivot_amplitude);
+        let phase = T::constant(self.pivot_frequency) * t.clone();
+        let zero = T::constant(0.0);
+        match self.pivot_motion {
+            PivotMotion::Fixed => (zero.clone(), zero),
+            PivotMotion::Vertical => (zero, a * phase.sin()),
+            PivotMotion::Horizontal => (a * phase.sin(), zero),
+            PivotMotion::Circular => {
+                let (sin, cos) = phase.sin_cos();
+                (a.clone() * cos, a * sin)
+            }
+            // Opaque to the arithmetic, so constant over a series step
+            PivotMotion::Custom => {
+                let position = self.pivot_function.as_ref().map_or(Vec2::new(0.0, 0.0), |f| {
+                    f(t.value()).0
+                });
+                (T::constant(position.x), T::constant(position.y))
+            }
+        }
+    }
+
+    // The uniform gravity field at time t in any arithmetic: the gravity vector scaled by
+    // 1 + A * sin(w * t), or what the gravity function gives, to first order about the float
+    // time (all a series step sees of it)
+    fn uniform_gravity<T: Scalar>(&self, t: &T) -> (T, T) {
+        if let Some(function) = &self.gravity_function {
+            let (field, rate) = function(t.value());
+            let offset = t.clone() - T::constant(t.value());
+            return (
+                T::constant(field.x) + T::constant(rate.x) * offset.clone(),
+                T::constant(field.y) + T::constant(rate.y) * offset,
+            );
+        }
+        let phase = T::constant(self.gravity_frequency) * t.clone();
+        let scale = T::constant(1.0) + T::constant(self.gravity_amplitude) * phase.sin();
+        (T::constant(self.gravity.x) * scale.clone(), T::constant(self.gravity.y) * scale)
+    }
+
+    // Whether the field itself changes in time (in the frame of the pivot), which puts
+    // energy into the chain or takes it out
+    fn gravity_varies(&self) -> bool {
+        let moving_pivot = self.cart.is_none() && self.pivot_motion != PivotMotion::Fixed;
+        self.gravity_function.is_some() ||
+            (self.gravity_amplitude != 0.0 && self.gravity_frequency != 0.0) ||
+            (self.gravity_source.is_some() && moving_pivot)
+    }
+
+    // Positions of the masses at time t and their masses, for fields that aren't uniform:
+    // the balls, the middles of the rods when they have inertia, and the cart
+    fn mass_points<T: Scalar>(&self, t: &T, thetas: &[T]) -> Vec<((T, T), f64)> {
+        let lengths = self.link_lengths(thetas);
+        let parents = self.parents();
+        let support = match self.cart_coordinate() {
+            Some(index) => (thetas[index].clone(), T::constant(0.0)),
+            None => self.pivot_offset(t),
+        };
+        let mut points = vec![];
+        if let Some(cart) = &self.cart {
+            points.push((support.clone(), cart.mass));
+        }
+        let mut ends: Vec<(T, T)> = Vec::with_capacity(self.balls.len());
+        for (i, ball) in self.balls.iter().enumerate() {
+            let (x, y) = match parents[i] {
+                Some(parent) => ends[parent].clone(),
+                None => {
+                    let offset = ball.pivot.unwrap_or_default();
+                    let (x, y) = support.clone();
+                    (x + T::constant(offset.x), y + T::constant(offset.y))
+                }
+            };
+            let (sin, cos) = thetas[i].sin_cos();
+            let (dx, dy) = (lengths[i].clone() * sin, lengths[i].clone() * cos);
+            if self.rod_inertia {
+                let half = T::constant(0.5);
+                let middle = (x.clone() + half.clone() * dx.clone(), y.clone() + half * dy.clone());
+                points.push((middle, ball.rod.mass));
+            }
+            let end = (x + dx, y + dy);
+            let mass = if self.mass_calculation { ball.mass } else { self.default_mass };
+            points.push((end.clone(), mass));
+            ends.push(end);
+        }
+        points
+    }
+
+    // Generalized gravity forces at time t. The uniform field g pulls on link i like the
+    // weight of moment_i at its end, moment_i * l_i * (g_x * cos(theta_i) - g_y * sin(theta_i)),
+    // and along an elastic one with moment_i * (g_x * sin(theta_i) + g_y * cos(theta_i)); the
+    // cart carries everything along g_x. The central source pulls each mass with its own
+    // force F, which link j feels through how it moves the mass, l_j * n_j per radian and e_j
+    // per unit of stretch for the masses below it (half that for the middle of its own rod).
+    fn gravity_forces<T: Scalar>(&self, t: &T, thetas: &[T]) -> Vec<T> {
+        let n = self.balls.len();
+        let distribution = self.mass_distribution();
+        let stretches = self.stretch_coordinates();
+        let lengths = self.link_lengths(thetas);
+        let (gx, gy) = self.uniform_gravity(t);
+        let mut q = vec![T::constant(0.0); self.coordinate_count()];
+        for i in 0..n {
+            let moment = T::constant(distribution.moment[i]);
+            let (sin, cos) = thetas[i].sin_cos();
+            q[i] =
+                moment.clone() *
+                lengths[i].clone() *
+                (gx.clone() * cos.clone() - gy.clone() * sin.clone());
+            if let Some(ri) = stretches[i] {
+                q[ri] = moment * (gx.clone() * sin + gy.clone() * cos);
+            }
+        }
+        if let (Some(cart), Some(x)) = (&self.cart, self.cart_coordinate()) {
+            q[x] = T::constant(cart.mass + distribution.total) * gx;
+        }
+
+        let Some(source) = &self.gravity_source else {
+            return q;
+        };
+        let pulls: Vec<(T, T)> = self
+            .mass_points(t, thetas)
+            .into_iter()
+            .map(|((x, y), mass)| {
+                let dx = T::constant(source.position.x) - x;
+                let dy = T::constant(source.position.y) - y;
+                let squared = dx.clone() * dx.clone() + dy.clone() * dy.clone();
+                let cubed = squared.clone() * squared.sqrt();
+                let scale = T::constant(source.strength * mass) / cubed;
+                (dx * scale.clone(), dy * scale)
+            })
+            .collect();
+        // mass_points goes cart, then per link its rod's middle (with rod inertia) and ball
+        let per_link = if self.rod_inertia { 2 } else { 1 };
+        let first = usize::from(self.cart.is_some());
+        let parents = self.parents();
+        // Pull on everything below each link, gathered up the chains, and across on all of them
+        let mut below = vec![(T::constant(0.0), T::constant(0.0)); n];
+        let mut across = T::constant(0.0);
+        for j in (0..n).rev() {
+            let (ball_x, ball_y) = pulls[first + per_link * j + per_link - 1].clone();
+            let (mut sx, mut sy) = below[j].clone();
+            sx = sx + ball_x;
+            sy = sy + ball_y;
+            let (mut hx, mut hy) = (sx.clone(), sy.clone());
+            if self.rod_inertia {
+                let (rod_x, rod_y) = pulls[first + per_link * j].clone();
+                let half = T::constant(0.5);
+                hx = hx + half.clone() * rod_x.clone();
+                hy = hy + half * rod_y.clone();
+                sx = sx + rod_x;
+                sy = sy + rod_y;
+            }
+            let (sin, cos) = thetas[j].sin_cos();
+            q[j] =
+                q[j].clone() +
+                lengths[j].clone() * (hx.clone() * cos.clone() - hy.clone() * sin.clone());
+            if let Some(rj) = stretches[j] {
+                q[rj] = q[rj].clone() + hx * sin + hy * cos;
+            }
+            match parents[j] {
+                Some(parent) => {
+                    let (px, py) = below[parent].clone();
+                    below[parent] = (px + sx, py + sy);
+                }
+                None => across = across + sx,
+            }
+        }
+        if let Some(x) = self.cart_coordinate() {
+            q[x] = q[x].clone() + across + pulls[0].0.clone();
+        }
+        q
+    }
+
+    // Rate at which the changing field puts energy into the chain, dU/dt at fixed
+    // coordinates
+    fn field_power(&self, t: f64, thetas: &[f64]) -> f64 {
+        let coordinates: Vec<Dual> = thetas
+            .iter()
+            .map(|&theta| Dual::new(theta, 0.0))
+            .collect();
+        self.potential_energy_at(&Dual::new(t, 1.0), &coordinates).derivative
+    }
+
+    // Generalized inertial forces of the accelerating pivot frame, None for a fixed pivot.
+    // Every mass feels -m * a like an extra uniform field, so in the terms of the gravity
+    // force, link i gets moment_i * l_i * (a_y * sin(theta_i) - a_x * cos(theta_i)), and the
+    // length of an elastic one -moment_i * (a_x * sin(theta_i) + a_y * cos(theta_i)).
+    fn pivot_forces<T: Scalar>(&self, t: &T, thetas: &[T]) -> Option<Vec<T>> {
+        let (ax, ay) = self.pivot_acceleration(t)?;
+        let distribution = self.mass_distribution();
+        let stretches = self.stretch_coordinates();
+        let lengths = self.link_lengths(thetas);
+        let mut q = vec![T::constant(0.0); self.coordinate_count()];
+        for i in 0..self.balls.len() {
+            let moment = T::constant(distribution.moment[i]);
+            let (sin, cos) = thetas[i].sin_cos();
+            q[i] =
+                moment.clone() *
+                lengths[i].clone() *
+                (ay.clone() * sin.clone() - ax.clone() * cos.clone());
+            if let Some(ri) = stretches[i] {
+                q[ri] = -(moment * (ax.clone() * sin + ay.clone() * cos));
+            }
+        }
+        Some(q)
+    }
+
+    // Whether anything feeds energy into the chain or takes it out
+    fn has_energy_flow(&self) -> bool {
+        self.has_drag() ||
+            self.gravity_varies() ||
+            self.cart.is_some() ||
+            self.pivot_motion != PivotMotion::Fixed ||
+            self.balls.iter().any(|ball| {
+                ball.viscous_friction != 0.0 ||
+                    ball.coulomb_friction != 0.0 ||
+                    ball.torque != 0.0 ||
+                    !ball.torque_schedule.is_empty() ||
+                    (ball.rod.is_elastic() && ball.rod.damping != 0.0)
+            })
+    }
+
+    // Rates at which friction, drag and spring damping take energy out of the chain (never
+    // negative), the moving pivot puts energy in and the motors and the cart force do work
+    fn power_flow(&self, t: f64, thetas: &[f64], theta_dots: &[f64]) -> EnergyFlow {
+        let mut flow = EnergyFlow::default();
+        let power = |forces: Vec<f64>| -> f64 {
+            forces
+                .iter()
+                .zip(theta_dots)
+                .map(|(q, omega)| q * omega)
+                .sum()
+        };
+        for i in 0..self.balls.len() {
+            if let Some(torque) = self.friction_torque(i, theta_dots) {
+                flow.dissipated -= torque * self.joint_velocity(i, theta_dots);
+            }
+            if let Some(torque) = self.joint_torque(i, &t) {
+                flow.actuated += torque * self.joint_velocity(i, theta_dots);
+            }
+        }
+        if let Some(inertial) = self.pivot_forces(&t, thetas) {
+            flow.pivot = power(inertial);
+        }
+        if let Some((drag, (fx, fy))) = self.drag_forces(&t, thetas, theta_dots) {
+            // The air takes the work of the drag against the bobs' velocity through it. The
+            // part from the pivot moving through the air is the pivot's.
+            let (vx, vy) = self.pivot_velocity(&t).unwrap_or((0.0, 0.0));
+            let pulled = fx * vx + fy * vy;
+            flow.dissipated -= power(drag) + pulled;
+            flow.pivot -= pulled;
+        }
+        if self.gravity_varies() {
+            flow.field = self.field_power(t, thetas);
+        }
+        for (stretch, ball) in self.stretch_coordinates().iter().zip(&self.balls) {
+            if let Some(index) = stretch {
+                flow.dissipated += ball.rod.damping * theta_dots[*index] * theta_dots[*index];
+            }
+        }
+        if let (Some(cart), Some(x)) = (&self.cart, self.cart_coordinate()) {
+            flow.dissipated += cart.friction * theta_dots[x] * theta_dots[x];
+            flow.actuated += cart.force * theta_dots[x];
+        }
+        flow
+    }
+
+    // Angular accelerations in any arithmetic, None if the mass matrix is singular
+    fn accelerations<T: Scalar>(&self, t: &T, thetas: &[T], theta_dots: &[T]) -> Option<Vec<T>> {
+        scalar::solve(self.mass_matrix_entries(thetas), self.force_entries(t, thetas, theta_dots))
+    }
+
+    // Time derivative of a [thetas; omegas] state in any arithmetic
+    fn generic_derivative<T: Scalar>(&self, t: &T, state: &[T]) -> Option<Vec<T>> {
+        let (thetas, theta_dots) = state.split_at(self.coordinate_count());
+        let theta_ddots = self.accelerations(t, thetas, theta_dots)?;
+        Some(theta_dots.iter().cloned().chain(theta_ddots).collect())
+    }
+
+    // Build the mass matrix M of M * theta_ddot = v
+    fn mass_matrix(&self, thetas: &DVector<f64>) -> DMatrix<f64> {
+        let n = self.coordinate_count();
+        DMatrix::from_row_slice(n, n, &self.mass_matrix_entries(thetas.as_slice()))
+    }
+
+    // Time derivative of the mass matrix while the angles move with theta_dots, by
+    // differentiating M along thetas + s * theta_dots
+    fn mass_matrix_rate(&self, thetas: &DVector<f64>, theta_dots: &DVector<f64>) -> DMatrix<f64> {
+        let n = self.coordinate_count();
+        let moving: Vec<Dual> = thetas
+            .iter()
+            .zip(theta_dots.iter())
+            .map(|(&theta, &theta_dot)| Dual::new(theta, theta_dot))
+            .collect();
+        let entries: Vec<f64> = self
+            .mass_matrix_entries(&moving)
+            .iter()
+            .map(|m| m.derivative)
+            .collect();
+        DMatrix::from_row_slice(n, n, &entries)
+    }
+
+    // Build the force vector v of M * theta_ddot = v
+    fn force_vector(
+        &self,
+        t: f64,
+        thetas: &DVector<f64>,
+        theta_dots: &DVector<f64>
+    ) -> DVector<f64> {
+        DVector::from_vec(self.force_entries(&t, thetas.as_slice(), theta_dots.as_slice()))
+    }
+
+    fn calculate_accelerations(
+        &self,
+        t: f64,
+        thetas: &DVector<f64>,
+        theta_dots: &DVector<f64>
+    ) -> (DVector<f64>, DVector<f64>) {
+        let n = self.coordinate_count();
+        let m = self.mass_matrix(thetas);
+        let v = self.force_vector(t, thetas, theta_dots);
+
+        // Solve M * theta_ddot = v for theta_ddot
+        let lu = LU::new(m);
+        let theta_ddots = lu.solve(&v).unwrap_or_else(|| DVector::from_element(n, 0.0));
+
+        (theta_dots.clone(), theta_ddots)
+    }
+
+    // Time derivative of the canonical state [thetas; momenta], where the momenta are
+    // p = M * theta_dot. Since M * theta_ddot = v, the momenta change as p_dot = v + M_dot * theta_dot.
+    // Returns None if the mass matrix is singular.
+    fn canonical_derivative(&self, t: f64, state: &DVector<f64>) -> Option<DVector<f64>> {
+        let n = self.coordinate_count();
+        let thetas = state.rows(0, n).into_owned();
+        let momenta = state.rows(n, n).into_owned();
+
+        let theta_dots = LU::new(self.mass_matrix(&thetas)).solve(&momenta)?;
+        let momentum_dots =
+            self.force_vector(t, &thetas, &theta_dots) +
+            self.mass_matrix_rate(&thetas, &theta_dots) * &theta_dots;
+
+        let mut derivative = DVector::from_element(2 * n, 0.0);
+        derivative.rows_mut(0, n).copy_from(&theta_dots);
+        derivative.rows_mut(n, n).copy_from(&momentum_dots);
+        Some(derivative)
     }
+
     pub fn reset(&mut self) {
         *self = Universe::new();
     }
+    #[allow(clippy::too_many_arguments)]
     pub fn add_ball(
         &mut self,
         px: f64,
@@ -649,6 +2230,7 @@ impl Universe {
     ) {
         self.balls.push(Ball::new(px, py, omega, theta, rl, rm, rc, radius, mass, color));
         self.update_initial_energy();
+        self.invalidate_history();
     }
 
     pub fn random_color() -> u32 {
@@ -662,14 +2244,15 @@ impl Universe {
         let default_color = Self::random_color();
         let default_rod_color = 0x0f0f0f;
 
-        // Calculate position from previous ball or origin
+        // Calculate position from previous ball or the pivot
         let (px, py) = if let Some(last_ball) = self.balls.last() {
             (
                 last_ball.pos.x + default_length * f64::sin(theta),
                 last_ball.pos.y + default_length * f64::cos(theta),
             )
         } else {
-            (default_length * f64::sin(theta), default_length * f64::cos(theta))
+            let pivot = self.pivot_position(self.time);
+            (pivot.x + default_length * f64::sin(theta), pivot.y + default_length * f64::cos(theta))
         };
 
         self.balls.push(
@@ -687,10 +2270,12 @@ impl Universe {
             )
         );
         self.update_initial_energy();
+        self.invalidate_history();
     }
     pub fn remove_ball(&mut self) {
         self.balls.pop();
         self.update_initial_energy();
+        self.invalidate_history();
     }
     pub fn get_balls(&self) -> JsValue {
         serde_wasm_bindgen::to_value(&self.balls).unwrap()
@@ -706,22 +2291,9 @@ impl Universe {
     pub fn update_ball_theta(&mut self, index: usize, theta: f64) {
         if index < self.balls.len() {
             self.balls[index].theta = theta;
-
-            // Recalculate positions for this ball and all subsequent balls
-            for i in index..self.balls.len() {
-                let (mut x, mut y) = if i == 0 {
-                    (0.0, 0.0)
-                } else {
-                    (self.balls[i - 1].pos.x, self.balls[i - 1].pos.y)
-                };
-
-                x += self.balls[i].rod.length * f64::sin(self.balls[i].theta);
-                y += self.balls[i].rod.length * f64::cos(self.balls[i].theta);
-
-                self.balls[i].pos.x = x;
-                self.balls[i].pos.y = y;
-            }
+            self.update_positions();
             self.update_initial_energy();
+            self.invalidate_history();
         }
     }
 
@@ -738,6 +2310,127 @@ impl Universe {
         if index < self.balls.len() {
             self.balls[index].mass = mass;
             self.update_initial_energy();
+            self.invalidate_history();
+        }
+    }
+
+    pub fn update_ball_rod_mass(&mut self, index: usize, rod_mass: f64) {
+        if index < self.balls.len() {
+            self.balls[index].rod.mass = rod_mass;
+            self.update_initial_energy();
+            self.invalidate_history();
+        }
+    }
+
+    // Make the rod above the ball a spring with the given rest length, stiffness and damping,
+    // starting from its current length. Its length becomes a coordinate of its own. Zero
+    // stiffness makes the rod rigid again at its current length. A soft spring lets the bob
+    // swing close past its joint, where the angle turns fast enough to need adaptive steps.
+    pub fn update_ball_rod_spring(
+        &mut self,
+        index: usize,
+        rest_length: f64,
+        stiffness: f64,
+        damping: f64
+    ) {
+        if index < self.balls.len() {
+            let rod = &mut self.balls[index].rod;
+            rod.rest_length = rest_length;
+            rod.stiffness = stiffness.max(0.0);
+            rod.damping = damping.max(0.0);
+            if !rod.is_elastic() {
+                rod.length_rate = 0.0;
+            }
+            self.update_initial_energy();
+            self.invalidate_history();
+        }
+    }
+
+    // Rate at which an elastic rod is stretching, ignored for rigid rods
+    pub fn update_ball_length_rate(&mut self, index: usize, length_rate: f64) {
+        if index < self.balls.len() && self.balls[index].rod.is_elastic() {
+            self.balls[index].rod.length_rate = length_rate;
+            self.update_initial_energy();
+            self.invalidate_history();
+        }
+    }
+
+    pub fn update_ball_viscous_friction(&mut self, index: usize, viscous_friction: f64) {
+        if index < self.balls.len() {
+            self.balls[index].viscous_friction = viscous_friction.max(0.0);
+            self.invalidate_history();
+        }
+    }
+
+    pub fn update_ball_coulomb_friction(&mut self, index: usize, coulomb_friction: f64) {
+        if index < self.balls.len() {
+            self.balls[index].coulomb_friction = coulomb_friction.max(0.0);
+            self.invalidate_history();
+        }
+    }
+
+    // Constant motor torque in the joint above the ball, replacing any schedule. Like the
+    // cart force, it can be set between time steps by a controller.
+    pub fn update_ball_torque(&mut self, index: usize, torque: f64) {
+        if index < self.balls.len() {
+            self.balls[index].torque = torque;
+            self.balls[index].torque_schedule.clear();
+            self.restart_integrator();
+        }
+    }
+
+    // Drive the joint above the ball through the (time, torque) points, linearly in between
+    // and holding the end values outside. Points beyond the shorter list are ignored.
+    pub fn set_ball_torque_schedule(&mut self, index: usize, times: Vec<f64>, torques: Vec<f64>) {
+        if index < self.balls.len() {
+            let mut schedule: Vec<(f64, f64)> = times.into_iter().zip(torques).collect();
+            schedule.sort_by(|a, b| a.0.total_cmp(&b.0));
+            self.balls[index].torque_schedule = schedule;
+            self.invalidate_history();
+        }
+    }
+
+    // Motor torque in the joint above the ball at the current time
+    pub fn get_ball_torque(&self, index: usize) -> f64 {
+        if index < self.balls.len() {
+            self.joint_torque(index, &self.time).unwrap_or(0.0)
+        } else {
+            0.0
+        }
+    }
+
+    // Stops on the angle of the joint above the ball relative to the link above it (to the
+    // vertical for the first ball), clamped to [-PI, PI]. Limits that cross after clamping
+    // are ignored. Hitting one bounces the joint back with the given fraction of its rate,
+    // 1 for an elastic stop and 0 for a dead one. A joint outside the new limits is put
+    // back on the nearer one.
+    pub fn update_ball_angle_limits(
+        &mut self,
+        index: usize,
+        min_angle: f64,
+        max_angle: f64,
+        restitution: f64
+    ) {
+        let (min_angle, max_angle) = (min_angle.clamp(-PI, PI), max_angle.clamp(-PI, PI));
+        if index < self.balls.len() && min_angle <= max_angle {
+            let ball = &mut self.balls[index];
+            ball.min_angle = min_angle;
+            ball.max_angle = max_angle;
+            ball.restitution = restitution.clamp(0.0, 1.0);
+            let (thetas, _) = self.coordinates();
+            if self.limit_clearances(thetas.as_slice(), index).iter().any(|&g| g < 0.0) {
+                self.resolve_limit(index);
+            }
+            self.update_initial_energy();
+            self.invalidate_history();
+        }
+    }
+
+    pub fn clear_ball_angle_limits(&mut self, index: usize) {
+        if index < self.balls.len() {
+            self.balls[index].min_angle = f64::NEG_INFINITY;
+            self.balls[index].max_angle = f64::INFINITY;
+            self.invalidate_history();
         }
     }
 
@@ -750,6 +2443,9 @@ impl Universe {
     pub fn update_ball_radius(&mut self, index: usize, radius: i32) {
         if index < self.balls.len() {
             self.balls[index].radius = radius;
+            if self.has_drag() {
+                self.invalidate_history(); // The radius sets the drag
+            }
         }
     }
 
@@ -757,6 +2453,7 @@ impl Universe {
         if index < self.balls.len() {
             self.balls[index].omega = omega;
             self.update_initial_energy();
+            self.invalidate_history();
         }
     }
 
@@ -772,17 +2469,80 @@ impl Universe {
             serde_wasm_bindgen::to_value(&trails).unwrap()
         }
     }
+    // Uniform gravity of the given strength straight down. Unlike set_gravity_vector it
+    // leaves the energy target and the work and dissipation tallies as they were.
     pub fn set_gravity(&mut self, gravity: f64) {
-        self.gravity = gravity;
+        self.gravity = Vec2::new(0.0, gravity);
+        self.gravity_function = None;
+        self.invalidate_history();
     }
+    // Downward part of the uniform field, what set_gravity set
     pub fn get_gravity(&self) -> f64 {
-        return self.gravity;
+        self.gravity.y
+    }
+    // Uniform gravity pointing any way (positive y is down). Replaces a gravity function.
+    pub fn set_gravity_vector(&mut self, x: f64, y: f64) {
+        self.gravity = Vec2::new(x, y);
+        self.gravity_function = None;
+        self.update_initial_energy();
+        self.invalidate_history();
+    }
+    pub fn get_gravity_vector(&self) -> Vec2 {
+        self.gravity
+    }
+    // Modulate the uniform field in time, g(t) = g * (1 + A * sin(w * t)) with relative
+    // amplitude A and angular frequency w. The energy the changing field puts in is tracked
+    // as field work. Near twice the natural frequency it pumps up small swings like a
+    // vertically driven pivot. Replaces a gravity function.
+    pub fn set_gravity_modulation(&mut self, amplitude: f64, frequency: f64) {
+        self.gravity_amplitude = amplitude;
+        self.gravity_frequency = frequency;
+        self.gravity_function = None;
+        self.update_initial_energy();
+        self.invalidate_history();
+    }
+    // Let the uniform field follow a JS function t => [gx, gy, rate_x, rate_y] giving the
+    // field and its rate of change, see set_gravity_function
+    pub fn set_gravity_callback(&mut self, callback: js_sys::Function) {
+        self.set_gravity_function(
+            Rc::new(move |t| {
+                let [field, rate] = call_vectors(&callback, t);
+                (field, rate)
+            })
+        );
+    }
+    pub fn get_gravity_amplitude(&self) -> f64 {
+        self.gravity_amplitude
+    }
+    pub fn get_gravity_frequency(&self) -> f64 {
+        self.gravity_frequency
+    }
+    // Add a central source of inverse-square gravity at (x, y), pulling each mass m at
+    // distance r toward it with strength * m / r^2. It adds to the uniform field, set that to
+    // zero for the source alone. Rod masses are pulled at their middles.
+    pub fn set_gravity_source(&mut self, x: f64, y: f64, strength: f64) {
+        self.gravity_source = Some(GravitySource { position: Vec2::new(x, y), strength });
+        self.update_initial_energy();
+        self.invalidate_history();
+    }
+    pub fn clear_gravity_source(&mut self) {
+        self.gravity_source = None;
+        self.update_initial_energy();
+        self.invalidate_history();
+    }
+    pub fn get_gravity_source(&self) -> Option<GravitySource> {
+        self.gravity_source
+    }
+    // Energy the changing gravity put into the chain since the last edit: a modulated or
+    // scripted field, or the central source as seen from a moving pivot
+    pub fn get_field_work(&self) -> f64 {
+        self.field_work
     }
     pub fn set_speed(&mut self, speed: f64) {
         self.speed = speed;
     }
     pub fn get_speed(&self) -> f64 {
-        return self.speed;
+        self.speed
     }
 
     pub fn set_is_paused(&mut self, is_paused: bool) {
@@ -790,49 +2550,943 @@ impl Universe {
     }
 
     pub fn get_is_paused(&self) -> bool {
-        return self.is_paused;
+        self.is_paused
     }
 
     pub fn set_implementation(&mut self, implementation: Implementation) {
         self.implementation = implementation;
+        self.custom_integrator = false;
+        self.integrator = None;
     }
     pub fn get_implementation(&self) -> Implementation {
-        return self.implementation;
+        self.implementation
     }
 
     pub fn set_mass_calculation(&mut self, mass_calculation: bool) {
         self.mass_calculation = mass_calculation;
+        self.update_initial_energy();
+        self.invalidate_history();
     }
 
     pub fn get_mass_calculation(&self) -> bool {
-        return self.mass_calculation;
+        self.mass_calculation
     }
 
     pub fn toggle_mass_calculation(&mut self) {
         self.mass_calculation = !self.mass_calculation;
+        self.update_initial_energy();
+        self.invalidate_history();
     }
 
-    pub fn set_show_trails(&mut self, show_trails: bool) {
-        self.show_trails = show_trails;
+    // Model the rods as uniform rigid bodies with their own mass and moment of inertia,
+    // or as massless links between point masses
+    pub fn set_rod_inertia(&mut self, rod_inertia: bool) {
+        self.rod_inertia = rod_inertia;
+        self.update_initial_energy();
+        self.invalidate_history();
     }
 
-    pub fn get_show_trails(&self) -> bool {
-        return self.show_trails;
+    pub fn get_rod_inertia(&self) -> bool {
+        self.rod_inertia
     }
 
-    pub fn toggle_show_trails(&mut self) {
-        self.show_trails = !self.show_trails;
+    pub fn toggle_rod_inertia(&mut self) {
+        self.set_rod_inertia(!self.rod_inertia);
     }
 
-    pub fn set_limit_total_energy(&mut self, limit_total_energy: bool) {
-        self.limit_total_energy = limit_total_energy;
+    // Relative joint velocity below which Coulomb friction holds a joint instead of slipping.
+    // Smaller is closer to ideal dry friction but stiffer to integrate.
+    pub fn set_stick_velocity(&mut self, stick_velocity: f64) {
+        self.stick_velocity = stick_velocity.abs().max(f64::MIN_POSITIVE);
+        self.invalidate_history();
     }
 
-    pub fn get_limit_total_energy(&self) -> bool {
-        return self.limit_total_energy;
+    pub fn get_stick_velocity(&self) -> f64 {
+        self.stick_velocity
     }
 
-    pub fn toggle_limit_total_energy(&mut self) {
-        self.limit_total_energy = !self.limit_total_energy;
+    // Density, dynamic viscosity and drag coefficient of the medium around the bobs, in the
+    // units of mass and length the chain uses. Zero density and viscosity is a vacuum.
+    pub fn set_medium(&mut self, density: f64, viscosity: f64, drag_coefficient: f64) {
+        self.medium_density = density.max(0.0);
+        self.medium_viscosity = viscosity.max(0.0);
+        self.drag_coefficient = drag_coefficient.max(0.0);
+        self.invalidate_history();
+    }
+
+    pub fn get_medium_density(&self) -> f64 {
+        self.medium_density
+    }
+
+    pub fn get_medium_viscosity(&self) -> f64 {
+        self.medium_viscosity
+    }
+
+    pub fn get_drag_coefficient(&self) -> f64 {
+        self.drag_coefficient
+    }
+
+    // Energy joint friction and drag took out of the chain since the last edit. Total energy
+    // plus this, minus the pivot work, stays at the initial energy up to integration error.
+    pub fn get_dissipated_energy(&self) -> f64 {
+        self.dissipated_energy
+    }
+
+    // Drive the pivot with amplitude A and angular frequency w (see PivotMotion). Its
+    // acceleration enters the equations as an inertial force and its velocity carries the
+    // bobs through the still medium. Angles, velocities and energies are measured in the
+    // moving frame of the pivot. A fast vertical drive with
+    // A^2 * w^2 > 2 * g * l keeps an inverted pendulum upright (Kapitza), and w near twice
+    // the natural frequency pumps up small swings (parametric resonance).
+    pub fn set_pivot_motion(&mut self, motion: PivotMotion, amplitude: f64, frequency: f64) {
+        self.pivot_motion = motion;
+        self.pivot_amplitude = amplitude;
+        self.pivot_frequency = frequency;
+        self.update_positions();
+        self.invalidate_history();
+    }
+
+    // Drive the pivot along a JS function t => [x, y, vx, vy, ax, ay] giving its position,
+    // velocity and acceleration, see set_pivot_function
+    pub fn set_pivot_callback(&mut self, callback: js_sys::Function) {
+        self.set_pivot_function(
+            Rc::new(move |t| {
+                let [position, velocity, acceleration] = call_vectors(&callback, t);
+                (position, velocity, acceleration)
+            })
+        );
+    }
+
+    pub fn get_pivot_motion(&self) -> PivotMotion {
+        self.pivot_motion
+    }
+
+    pub fn get_pivot_amplitude(&self) -> f64 {
+        self.pivot_amplitude
+    }
+
+    pub fn get_pivot_frequency(&self) -> f64 {
+        self.pivot_frequency
+    }
+
+    // Where the pivot is now, the point the first rod hangs from
+    pub fn get_pivot(&self) -> Vec2 {
+        self.pivot_position(self.time)
+    }
+
+    // Energy the moving pivot put into the chain since the last edit (negative if it took
+    // energy out)
+    pub fn get_pivot_work(&self) -> f64 {
+        self.pivot_work
+    }
+
+    // Start another chain hanging from (dx, dy) relative to the pivot, with one default ball
+    // at angle theta. Further balls added with add_ball_simple hang below it.
+    pub fn add_chain(&mut self, dx: f64, dy: f64, theta: f64) {
+        self.add_ball_simple(theta);
+        let index = self.balls.len() - 1;
+        self.balls[index].pivot = Some(Vec2::new(dx, dy));
+        self.update_positions();
+        self.update_initial_energy();
+        self.invalidate_history();
+    }
+
+    // Move a chain's pivot to (dx, dy) relative to the pivot, carrying its balls along
+    pub fn set_chain_pivot(&mut self, chain: usize, dx: f64, dy: f64) {
+        if let Some(&root) = self.chain_roots().get(chain) {
+            self.balls[root].pivot = Some(Vec2::new(dx, dy));
+            self.update_positions();
+            self.update_initial_energy();
+            self.invalidate_history();
+        }
+    }
+
+    // Hang another default ball at angle theta from the given ball, next to any links
+    // already hanging from it
+    pub fn add_ball_below(&mut self, parent: usize, theta: f64) {
+        if parent < self.balls.len() {
+            self.add_ball_simple(theta);
+            let index = self.balls.len() - 1;
+            self.balls[index].parent = Some(parent);
+            self.update_positions();
+            self.update_initial_energy();
+            self.invalidate_history();
+        }
+    }
+
+    // Hang a ball's link from an earlier ball, carrying the balls below it along. A ball
+    // that started a chain joins the parent's chain.
+    pub fn set_ball_parent(&mut self, index: usize, parent: usize) {
+        if parent < index && index < self.balls.len() {
+            self.balls[index].pivot = None;
+            self.balls[index].parent = Some(parent);
+            self.update_positions();
+            self.update_initial_energy();
+            self.invalidate_history();
+        }
+    }
+
+    // The ball a ball's link hangs from, None for the first ball of a chain
+    pub fn get_ball_parent(&self, index: usize) -> Option<usize> {
+        if index < self.balls.len() { self.parent(index) } else { None }
+    }
+
+    pub fn get_chain_count(&self) -> usize {
+        self.chain_roots().len()
+    }
+
+    // Where each chain hangs from now, in order of their first balls
+    pub fn get_chain_pivots(&self) -> Vec<Vec2> {
+        let pivot = self.pivot_position(self.time);
+        self.chain_roots()
+            .into_iter()
+            .map(|root| pivot + self.balls[root].pivot.unwrap_or_default())
+            .collect()
+    }
+
+    // Where the ball's link hangs from now if the ball starts a chain: its chain's pivot,
+    // carried by the pivot motion or the cart. None if it hangs from another ball.
+    pub fn get_ball_pivot(&self, index: usize) -> Option<Vec2> {
+        if index >= self.balls.len() || self.parent(index).is_some() {
+            return None;
+        }
+        Some(self.pivot_position(self.time) + self.balls[index].pivot.unwrap_or_default())
+    }
+
+    // The chain a ball belongs to, numbered as in get_chain_pivots
+    pub fn get_ball_chain(&self, index: usize) -> Option<usize> {
+        if index >= self.balls.len() {
+            return None;
+        }
+        let mut root = index;
+        while let Some(parent) = self.parent(root) {
+            root = parent;
+        }
+        self.chain_roots().iter().position(|&r| r == root)
+    }
+
+    // Hang the chain from a cart of the given mass on a horizontal rail (cart-pole), at rest
+    // under the current pivot. The cart position becomes a coordinate of the dynamics, and
+    // takes the place of any pivot motion while enabled.
+    pub fn enable_cart(&mut self, mass: f64) {
+        let position = self.pivot_position(self.time).x;
+        self.cart = Some(Cart { position, velocity: 0.0, mass, friction: 0.0, force: 0.0 });
+        self.update_positions();
+        self.update_initial_energy();
+        self.invalidate_history();
+    }
+
+    pub fn disable_cart(&mut self) {
+        self.cart = None;
+        self.update_positions();
+        self.update_initial_energy();
+        self.invalidate_history();
+    }
+
+    pub fn get_cart_enabled(&self) -> bool {
+        self.cart.is_some()
+    }
+
+    pub fn get_cart(&self) -> Option<Cart> {
+        self.cart
+    }
+
+    pub fn set_cart_mass(&mut self, mass: f64) {
+        if let Some(cart) = &mut self.cart {
+            cart.mass = mass;
+            self.update_initial_energy();
+            self.invalidate_history();
+        }
+    }
+
+    pub fn set_cart_friction(&mut self, friction: f64) {
+        if let Some(cart) = &mut self.cart {
+            cart.friction = friction;
+            self.invalidate_history();
+        }
+    }
+
+    // Horizontal force on the cart, held until changed. Meant to be set between time steps
+    // by a controller, so the reference run and the enclosure carry on.
+    pub fn set_cart_force(&mut self, force: f64) {
+        if let Some(cart) = &mut self.cart {
+            cart.force = force;
+            self.restart_integrator();
+        }
+    }
+
+    pub fn set_cart_position(&mut self, position: f64) {
+        if let Some(cart) = &mut self.cart {
+            cart.position = position;
+            self.update_positions();
+            self.invalidate_history();
+        }
+    }
+
+    pub fn set_cart_velocity(&mut self, velocity: f64) {
+        if let Some(cart) = &mut self.cart {
+            cart.velocity = velocity;
+            self.update_initial_energy();
+            self.invalidate_history();
+        }
+    }
+
+    // Work the joint motors and the cart force did on the system since the last edit. The
+    // total energy is the energy after the edit plus this, plus the pivot and field work,
+    // minus the dissipated energy.
+    pub fn get_actuator_work(&self) -> f64 {
+        self.actuator_work
+    }
+
+    pub fn set_show_trails(&mut self, show_trails: bool) {
+        self.show_trails = show_trails;
+    }
+
+    pub fn get_show_trails(&self) -> bool {
+        self.show_trails
+    }
+
+    pub fn toggle_show_trails(&mut self) {
+        self.show_trails = !self.show_trails;
+    }
+
+    pub fn set_limit_total_energy(&mut self, limit_total_energy: bool) {
+        self.limit_total_energy = limit_total_energy;
+    }
+
+    pub fn get_limit_total_energy(&self) -> bool {
+        self.limit_total_energy
+    }
+
+    pub fn toggle_limit_total_energy(&mut self) {
+        self.limit_total_energy = !self.limit_total_energy;
+    }
+
+    pub fn set_energy_limit(&mut self, energy_limit: EnergyLimit) {
+        self.energy_limit = energy_limit;
+    }
+
+    pub fn get_energy_limit(&self) -> EnergyLimit {
+        self.energy_limit
+    }
+
+    // Size of the last energy correction, as the Euclidean norm of the change in (theta, omega)
+    pub fn get_last_energy_correction(&self) -> f64 {
+        self.last_energy_correction
+    }
+
+    // Sum of all energy correction sizes since the last reset_energy_correction
+    pub fn get_total_energy_correction(&self) -> f64 {
+        self.total_energy_correction
+    }
+
+    pub fn reset_energy_correction(&mut self) {
+        self.last_energy_correction = 0.0;
+        self.total_energy_correction = 0.0;
+    }
+
+    pub fn set_abs_tolerance(&mut self, abs_tolerance: f64) {
+        self.abs_tolerance = abs_tolerance;
+        self.rebuild_integrator();
+    }
+
+    pub fn get_abs_tolerance(&self) -> f64 {
+        self.abs_tolerance
+    }
+
+    pub fn set_rel_tolerance(&mut self, rel_tolerance: f64) {
+        self.rel_tolerance = rel_tolerance;
+        self.rebuild_integrator();
+    }
+
+    pub fn get_rel_tolerance(&self) -> f64 {
+        self.rel_tolerance
+    }
+
+    pub fn get_step_statistics(&self) -> StepStatistics {
+        self.integrator
+            .as_ref()
+            .map(|integrator| integrator.statistics())
+            .unwrap_or_default()
+    }
+
+    // Number of adaptive steps accepted since the last reset_step_statistics
+    pub fn get_accepted_steps(&self) -> u32 {
+        self.get_step_statistics().accepted_steps
+    }
+
+    // Number of steps rejected (and retried smaller) since the last reset, by the adaptive
+    // integrators or by Gauss–Legendre when its stage iteration doesn't converge
+    pub fn get_rejected_steps(&self) -> u32 {
+        self.get_step_statistics().rejected_steps
+    }
+
+    // Size of the last accepted step (negative when running backwards)
+    pub fn get_last_step_size(&self) -> f64 {
+        self.get_step_statistics().last_step_size
+    }
+
+    pub fn reset_step_statistics(&mut self) {
+        if let Some(integrator) = &mut self.integrator {
+            integrator.reset_statistics();
+        }
+    }
+
+    // Estimated local error of the last accepted adaptive step, as a fraction of the
+    // tolerances (below 1 means the step met them)
+    pub fn get_error_estimate(&self) -> f64 {
+        self.get_step_statistics().error_estimate
+    }
+
+    // Number of extrapolation columns Bulirsch–Stoer may use before halving the step
+    pub fn set_extrapolation_order(&mut self, extrapolation_order: u32) {
+        self.extrapolation_order = extrapolation_order.max(2);
+        self.rebuild_integrator();
+    }
+
+    pub fn get_extrapolation_order(&self) -> u32 {
+        self.extrapolation_order
+    }
+
+    // Order of the Taylor series integrator, i.e. how many derivatives it takes per step
+    pub fn set_taylor_order(&mut self, taylor_order: u32) {
+        self.taylor_order = taylor_order.clamp(2, 60);
+        self.rebuild_integrator();
+    }
+
+    pub fn get_taylor_order(&self) -> u32 {
+        self.taylor_order
+    }
+
+    // Tolerance of the fixed-point solve used by the implicit Gauss–Legendre and variational
+    // integrators, and of the Newton solve of the BDF integrators
+    pub fn set_implicit_tolerance(&mut self, implicit_tolerance: f64) {
+        self.implicit_tolerance = implicit_tolerance;
+        self.rebuild_integrator();
+    }
+
+    pub fn get_implicit_tolerance(&self) -> f64 {
+        self.implicit_tolerance
+    }
+
+    pub fn set_implicit_max_iterations(&mut self, implicit_max_iterations: u32) {
+        self.implicit_max_iterations = implicit_max_iterations.max(1);
+        self.rebuild_integrator();
+    }
+
+    pub fn get_implicit_max_iterations(&self) -> u32 {
+        self.implicit_max_iterations
+    }
+
+    // Fixed-point iterations used by the last implicit step
+    pub fn get_last_implicit_iterations(&self) -> u32 {
+        self.get_step_statistics().iterations
+    }
+
+    // Simulated time in seconds
+    pub fn get_time(&self) -> f64 {
+        self.time
+    }
+
+    // Run the simulation backwards. Only takes effect while the implementation is time
+    // symmetric (see get_is_reversible); other integrators keep running forwards.
+    pub fn set_time_reversed(&mut self, time_reversed: bool) {
+        self.time_reversed = time_reversed;
+    }
+
+    pub fn get_time_reversed(&self) -> bool {
+        self.time_reversed
+    }
+
+    pub fn toggle_time_reversed(&mut self) {
+        self.time_reversed = !self.time_reversed;
+    }
+
+    // Whether the current integrator can run backwards
+    pub fn get_is_reversible(&self) -> bool {
+        match &self.integrator {
+            Some(integrator) => integrator.is_time_symmetric(),
+            None => self.build_integrator().is_time_symmetric(),
+        }
+    }
+
+    // Integrate `duration` seconds forwards in steps of `dt`, then the same steps
+    // backwards, on a copy of the universe. Returns how far the copy ends up from the
+    // current state (Euclidean norm over angles and angular velocities), or NaN if a
+    // step failed. The copy runs the bare integrator, without shadow runs, events or energy
+    // limiting.
+    pub fn round_trip_error(&self, duration: f64, dt: f64) -> f64 {
+        if dt <= 0.0 || self.balls.is_empty() {
+            return f64::NAN;
+        }
+        let steps = (duration.abs() / dt).ceil() as usize;
+        let mut copy = self.clone();
+        copy.invalidate_history();
+        copy.reference = None;
+        copy.enclosure = None;
+        copy.limit_total_energy = false;
+        copy.detect_bottom_passes = false;
+        copy.detect_link_flips = false;
+        copy.event_functions.clear();
+        for step in [dt, -dt] {
+            for _ in 0..steps {
+                if copy.single_physics_step(step) != 0 {
+                    return f64::NAN;
+                }
+            }
+        }
+
+        let (thetas, theta_dots) = copy.coordinates();
+        let (start_thetas, start_theta_dots) = self.coordinates();
+        let mut error = 0.0;
+        for i in 0..thetas.len() {
+            let mut difference = thetas[i] - start_thetas[i];
+            if i < self.balls.len() {
+                difference = Self::normalize_angle(difference);
+            }
+            error += f64::powi(difference, 2);
+            error += f64::powi(theta_dots[i] - start_theta_dots[i], 2);
+        }
+        f64::sqrt(error)
+    }
+
+    // Shadow the simulation with a double-double precision run (about 32 digits) of the
+    // same chain, starting from the current state, to tell real dynamics from round-off
+    pub fn start_reference(&mut self) {
+        self.reference = Some(Reference::new(self));
+    }
+
+    pub fn stop_reference(&mut self) {
+        self.reference = None;
+    }
+
+    pub fn get_reference_active(&self) -> bool {
+        self.reference.is_some()
+    }
+
+    // Largest difference in any angle or angular velocity between the simulation and the
+    // reference run after the last frame, NaN without a reference run
+    pub fn get_reference_divergence(&self) -> f64 {
+        self.reference.as_ref().map_or(f64::NAN, |reference| reference.divergence())
+    }
+
+    // Time at which the divergence first exceeded the threshold. Edits restart the reference
+    // run from the new state but keep this until start_reference.
+    pub fn get_divergence_time(&self) -> Option<f64> {
+        self.reference.as_ref().and_then(|reference| reference.divergence_time())
+    }
+
+    // Whether an impact stopped the reference run, which then stays at the impact time
+    pub fn get_reference_interrupted(&self) -> bool {
+        self.reference.as_ref().is_some_and(|reference| reference.is_interrupted())
+    }
+
+    pub fn set_divergence_threshold(&mut self, divergence_threshold: f64) {
+        self.divergence_threshold = divergence_threshold.abs();
+    }
+
+    pub fn get_divergence_threshold(&self) -> f64 {
+        self.divergence_threshold
+    }
+
+    pub fn get_reference_thetas(&self) -> Vec<f64> {
+        self.reference.as_ref().map_or(vec![], |reference| reference.thetas())
+    }
+
+    pub fn get_reference_omegas(&self) -> Vec<f64> {
+        self.reference.as_ref().map_or(vec![], |reference| reference.omegas())
+    }
+
+    // Carry rigorous interval enclosures of every theta and omega along with the simulation,
+    // starting from boxes of initial_width around the current state. They stop once wider
+    // than the enclosure threshold, which is when the simulation stops being trustworthy.
+    pub fn start_enclosure(&mut self, initial_width: f64) {
+        self.enclosure = Some(Enclosure::new(self, initial_width.abs()));
+    }
+
+    pub fn stop_enclosure(&mut self) {
+        self.enclosure = None;
+    }
+
+    pub fn get_enclosure_active(&self) -> bool {
+        self.enclosure.is_some()
+    }
+
+    // Width of the widest enclosure, NaN without an enclosure run
+    pub fn get_enclosure_width(&self) -> f64 {
+        self.enclosure.as_ref().map_or(f64::NAN, |enclosure| enclosure.width())
+    }
+
+    // Time at which the enclosures first grew wider than the threshold. Edits restart the
+    // enclosures from the new state but keep this until start_enclosure.
+    pub fn get_enclosure_loss_time(&self) -> Option<f64> {
+        self.enclosure.as_ref().and_then(|enclosure| enclosure.loss_time())
+    }
+
+    pub fn set_enclosure_threshold(&mut self, enclosure_threshold: f64) {
+        self.enclosure_threshold = enclosure_threshold.abs();
+    }
+
+    pub fn get_enclosure_threshold(&self) -> f64 {
+        self.enclosure_threshold
+    }
+
+    // Lower and upper bounds in the [thetas; omegas] layout
+    pub fn get_enclosure_lower(&self) -> Vec<f64> {
+        self.enclosure.as_ref().map_or(vec![], |enclosure| enclosure.lower())
+    }
+
+    pub fn get_enclosure_upper(&self) -> Vec<f64> {
+        self.enclosure.as_ref().map_or(vec![], |enclosure| enclosure.upper())
+    }
+
+    // Record an event whenever a link passes straight down
+    pub fn set_detect_bottom_passes(&mut self, detect_bottom_passes: bool) {
+        self.detect_bottom_passes = detect_bottom_passes;
+    }
+
+    pub fn get_detect_bottom_passes(&self) -> bool {
+        self.detect_bottom_passes
+    }
+
+    // Record an event whenever a link passes straight up over its pivot
+    pub fn set_detect_link_flips(&mut self, detect_link_flips: bool) {
+        self.detect_link_flips = detect_link_flips;
+    }
+
+    pub fn get_detect_link_flips(&self) -> bool {
+        self.detect_link_flips
+    }
+
+    // Events found during the last time_step, in the order they happened
+    pub fn get_events(&self) -> Vec<Event> {
+        self.events.clone()
+    }
+
+    pub fn get_event_count(&self) -> usize {
+        self.events.len()
+    }
+
+    // Watch a JS function (time, thetas, omegas) => number the same way as
+    // add_event_function. A result that isn't a number never fires.
+    pub fn add_event_callback(&mut self, callback: js_sys::Function) -> usize {
+        self.add_event_function(
+            Rc::new(move |t, thetas, omegas| {
+                let thetas = js_sys::Float64Array::from(thetas);
+                let omegas = js_sys::Float64Array::from(omegas);
+                callback
+                    .call3(&JsValue::NULL, &JsValue::from(t), &thetas, &omegas)
+                    .ok()
+                    .and_then(|value| value.as_f64())
+                    .unwrap_or(f64::NAN)
+            })
+        )
+    }
+
+    pub fn remove_event_function(&mut self, id: usize) {
+        self.event_functions.retain(|(function_id, _)| *function_id != id);
+    }
+
+    pub fn clear_event_functions(&mut self) {
+        self.event_functions.clear();
+    }
+
+    pub fn set_fixed_timestep(&mut self, fixed_timestep: bool) {
+        self.fixed_timestep = fixed_timestep;
+        self.accumulator = 0.0;
+        self.interpolation_factor = 1.0;
+        self.previous_thetas.clear();
+    }
+
+    pub fn get_fixed_timestep(&self) -> bool {
+        self.fixed_timestep
+    }
+
+    pub fn toggle_fixed_timestep(&mut self) {
+        self.set_fixed_timestep(!self.fixed_timestep);
+    }
+
+    // Size of the internal step used in fixed timestep mode, in simulated seconds
+    pub fn set_fixed_dt(&mut self, fixed_dt: f64) {
+        if fixed_dt > 0.0 {
+            self.fixed_dt = fixed_dt;
+        }
+    }
+
+    pub fn get_fixed_dt(&self) -> f64 {
+        self.fixed_dt
+    }
+
+    // How far the render time is between the last two fixed steps, from 0 to 1.
+    // Always 1 outside fixed timestep mode, where the balls are drawn where they are.
+    pub fn get_interpolation_factor(&self) -> f64 {
+        if self.fixed_timestep { self.interpolation_factor } else { 1.0 }
+    }
+
+    // Ball positions at the render time. The angles are interpolated (rather than the
+    // positions) so rods keep their length.
+    pub fn get_interpolated_positions(&self) -> Vec<Vec2> {
+        let alpha = self.get_interpolation_factor();
+        let pivot = self.pivot_position(self.time);
+        let pivot = if self.previous_thetas.is_empty() {
+            pivot
+        } else {
+            self.previous_pivot + (pivot - self.previous_pivot) * alpha
+        };
+        let mut positions: Vec<Vec2> = Vec::with_capacity(self.balls.len());
+        for (i, ball) in self.balls.iter().enumerate() {
+            let theta = match self.previous_thetas.get(i) {
+                Some(&previous) => previous + Self::normalize_angle(ball.theta - previous) * alpha,
+                None => ball.theta,
+            };
+            let start = match self.parent(i) {
+                Some(parent) => positions[parent],
+                None => pivot + ball.pivot.unwrap_or_default(),
+            };
+            let offset = Vec2::new(f64::sin(theta), f64::cos(theta)) * ball.rod.length;
+            positions.push(start + offset);
+        }
+        positions
+    }
+
+    pub fn get_kinetic_energy(&self) -> f64 {
+        self.calculate_kinetic_energy()
+    }
+
+    pub fn get_potential_energy(&self) -> f64 {
+        self.calculate_potential_energy()
+    }
+
+    pub fn get_total_energy(&self) -> f64 {
+        self.calculate_potential_energy() + self.calculate_kinetic_energy()
+    }
+
+    // Total angular momentum, each chain's about its own pivot: the sum of the momenta
+    // p = M * theta_dot conjugate to the angles. Conserved when gravity is off.
+    pub fn get_angular_momentum(&self) -> f64 {
+        let state = self.pack_state(StateSpace::Momenta);
+        state.rows(self.coordinate_count(), self.balls.len()).sum()
+    }
+}
+
+// Mass of the chain collected per link. Every mass point (a bob, or the centre of a rod)
+// sits at p = sum_j c_j * l_j * (sin theta_j, cos theta_j), with c_j = 1 for the links it hangs
+// below and c_j = 1/2 on its own link for a rod centre. Summed over the points
+//   coupling_ij = sum m * c_i * c_j   M_ij = coupling_ij * l_i * l_j * cos(theta_i - theta_j) (+ spin_i * l_i^2 if i == j)
+//   moment_j    = sum m * c_j         U = -g * sum_j moment_j * l_j * cos(theta_j)
+// For bobs alone coupling_ij is the mass below the lower of the two links if one hangs from
+// the other, and zero for links on different branches or chains.
+struct MassDistribution {
+    coupling: Vec<f64>, // n x n, row by row
+    moment: Vec<f64>,
+    spin: Vec<f64>, // m / 12 of each rod, for its motion about its centre
+    total: f64, // Mass of all the chains, what a cart carries along
+}
+
+// How close to a stop a joint counts as on it, in radians
+const LIMIT_TOLERANCE: f64 = 1e-9;
+
+// A joint running into its lower (stop 0) or upper (stop 1) stop, as found on a step's
+// interpolant: the fraction of the step where it hits and one where it's past the stop
+#[derive(Clone, Copy)]
+struct Impact {
+    index: usize,
+    stop: usize,
+    past: f64,
+    guess: f64,
+}
+
+// What a step changes, to take it again shorter when a joint hits one of its stops partway
+struct Checkpoint {
+    state: DVector<f64>, // Velocities layout
+    time: f64,
+    dissipated_energy: f64,
+    pivot_work: f64,
+    actuator_work: f64,
+    field_work: f64,
+    last_energy_correction: f64,
+    total_energy_correction: f64,
+    events: usize, // How many events had been found
+}
+
+// Energy friction and drag took out of the chain, the moving pivot put in, the applied
+// forces did on it and the changing gravity put in, over a step or per unit time
+#[derive(Clone, Copy, Default)]
+struct EnergyFlow {
+    dissipated: f64,
+    pivot: f64,
+    actuated: f64,
+    field: f64,
+}
+
+impl ops::Add for EnergyFlow {
+    type Output = EnergyFlow;
+    fn add(self, rhs: Self) -> Self::Output {
+        EnergyFlow {
+            dissipated: self.dissipated + rhs.dissipated,
+            pivot: self.pivot + rhs.pivot,
+            actuated: self.actuated + rhs.actuated,
+            field: self.field + rhs.field,
+        }
+    }
+}
+
+impl ops::Mul<f64> for EnergyFlow {
+    type Output = EnergyFlow;
+    fn mul(self, rhs: f64) -> Self::Output {
+        EnergyFlow {
+            dissipated: self.dissipated * rhs,
+            pivot: self.pivot * rhs,
+            actuated: self.actuated * rhs,
+            field: self.field * rhs,
+        }
+    }
+}
+
+// The universe's equations of motion in the state layout an integrator works on
+struct ChainDynamics<'a> {
+    universe: &'a Universe,
+    space: StateSpace,
+    // Energy that flowed over the accepted internal steps, if the integrator reported any
+    flow: Cell<Option<EnergyFlow>>,
+}
+
+impl Dynamics for ChainDynamics<'_> {
+    fn derivative(&self, t: f64, state: &DVector<f64>) -> Option<DVector<f64>> {
+        self.universe.derivative(self.space, t, state)
+    }
+
+    fn mass_matrix(&self, thetas: &DVector<f64>) -> DMatrix<f64> {
+        self.universe.mass_matrix(thetas)
+    }
+
+    // Exact Jacobian of the [thetas; omegas] equations by forward-mode differentiation, one
+    // dual number pass per column
+    fn jacobian(&self, t: f64, state: &DVector<f64>) -> Option<DMatrix<f64>> {
+        if self.space == StateSpace::Momenta {
+            return finite_difference_jacobian(&|t, y| self.derivative(t, y), t, state);
+        }
+        let n = state.len();
+        let mut jacobian = DMatrix::from_element(n, n, 0.0);
+        for j in 0..n {
+            let seeded: Vec<Dual> = state
+                .iter()
+                .enumerate()
+                .map(|(i, &y)| Dual::new(y, if i == j { 1.0 } else { 0.0 }))
+                .collect();
+            let column = self.universe.generic_derivative(&Dual::constant(t), &seeded)?;
+            for (i, entry) in column.iter().enumerate() {
+                jacobian[(i, j)] = entry.derivative;
+            }
+        }
+        Some(jacobian)
+    }
+
+    fn series_derivative(
+        &self,
+        t: &Series<f64>,
+        state: &[Series<f64>]
+    ) -> Option<Vec<Series<f64>>> {
+        self.universe.generic_derivative(t, state)
+    }
+
+    // Simpson's rule over the step, with the midpoint state from the cubic Hermite
+    // interpolant: adaptive steps can be long enough for the trapezoidal rule to lose track.
+    // Steps long enough to turn a link by more than PIECE_TURN (extrapolation steps can span
+    // whole frames) are split into pieces, with the states in between from RK4.
+    fn accepted_step(&self, t: f64, before: &DVector<f64>, after: &DVector<f64>, dt: f64) {
+        const PIECE_TURN: f64 = 0.1;
+        const MAX_PIECES: usize = 64;
+
+        let universe = self.universe;
+        if !universe.has_energy_flow() {
+            self.flow.set(Some(self.flow.get().unwrap_or_default()));
+            return;
+        }
+        let n = universe.coordinate_count();
+        let power = |t: f64, state: &DVector<f64>| {
+            universe
+                .unpacked_theta_dots(self.space, state)
+                .map_or(EnergyFlow::default(), |theta_dots| {
+                    universe.power_flow(t, state.rows(0, n).as_slice(), theta_dots.as_slice())
+                })
+        };
+        let simpson = |t: f64, before: &DVector<f64>, after: &DVector<f64>, dt: f64| {
+            let middle = match (self.derivative(t, before), self.derivative(t + dt, after)) {
+                (Some(f0), Some(f1)) => (before + after) * 0.5 + (f0 - f1) * (dt / 8.0),
+                _ => (before + after) * 0.5,
+            };
+            (power(t, before) + power(t + 0.5 * dt, &middle) * 4.0 + power(t + dt, after)) *
+                (dt / 6.0)
+        };
+
+        let links = universe.balls.len();
+        let mut turn: f64 = 0.0;
+        for f in [self.derivative(t, before), self.derivative(t + dt, after)].iter().flatten() {
+            turn = turn.max(f.rows(0, links).amax() * dt.abs());
+        }
+        let pieces = ((turn / PIECE_TURN).ceil() as usize).clamp(1, MAX_PIECES);
+        let h = dt / (pieces as f64);
+
+        let mut step = EnergyFlow::default();
+        let mut start = before.clone();
+        for k in 0..pieces {
+            let time = t + (k as f64) * h;
+            let end = if k + 1 == pieces {
+                after.clone()
+            } else {
+                match RungeKutta4.step(&|t, y| self.derivative(t, y), time, &start, h) {
+                    Some(end) => end,
+                    None => {
+                        // Fall back to a single piece over the rest of the step
+                        step = step + simpson(time, &start, after, t + dt - time);
+                        break;
+                    }
+                }
+            };
+            step = step + simpson(time, &start, &end, h);
+            start = end;
+        }
+        self.flow.set(Some(self.flow.get().unwrap_or_default() + step));
+    }
+
+    fn is_angle(&self, index: usize) -> bool {
+        index < self.universe.balls.len()
+    }
+}
+
+impl Universe {
+    // Plug in a custom integrator. It stays active until the next set_implementation.
+    pub fn set_integrator(&mut self, integrator: Box<dyn Integrator>) {
+        self.integrator = Some(integrator);
+        self.custom_integrator = true;
+    }
+
+    // Drive the pivot along a trajectory of its own: the function gives the position,
+    // velocity and acceleration of the pivot at a time. The Taylor integrator, the reference run and the
+    // enclosure can't expand it in time, so they hold the acceleration over each of their
+    // steps (the enclosure is no longer rigorous).
+    pub fn set_pivot_function(&mut self, function: PivotFunction) {
+        self.pivot_function = Some(function);
+        self.pivot_motion = PivotMotion::Custom;
+        self.update_positions();
+        self.invalidate_history();
+    }
+
+    // Let the uniform field follow a history of its own: the function gives the field and
+    // its rate of change at a time. Set a gravity vector or modulation to go back.
+    pub fn set_gravity_function(&mut self, function: GravityFunction) {
+        self.gravity_function = Some(function);
+        self.update_initial_energy();
+        self.invalidate_history();
+    }
+
+    // Watch a function of (time, thetas, omegas). Each sign change during a step is
+    // recorded as an EventKind::Custom event carrying the returned id.
+    pub fn add_event_function(&mut self, function: EventFunction) -> usize {
+        let id = self.next_event_function_id;
+        self.next_event_function_id += 1;
+        self.event_functions.push((id, function));
+        id
     }
 }
@@ -0,0 +1,128 @@
+use serde::{ Serialize, Deserialize };
+use crate::double_double::DoubleDouble;
+use crate::scalar::Scalar;
+use crate::series::{ taylor_coefficients, taylor_step_size };
+use crate::Universe;
+
+// Taylor order and relative tolerance of the reference run, close to double-double precision
+const ORDER: usize = 30;
+const TOLERANCE: f64 = 1e-30;
+
+// Shadow copy of the chain state in double-double precision, integrated with the same chain
+// model as the f64 state so the two can be compared while the simulation runs
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Reference {
+    time: f64,
+    state: Vec<DoubleDouble>, // [thetas; omegas], with the cart after the angles
+    links: usize,
+    divergence: f64,
+    divergence_time: Option<f64>,
+    earlier_divergence_time: Option<f64>, // Divergence of a run before the last restart
+    interrupted: bool, // The chain left the smooth dynamics, at an impact
+}
+
+impl Reference {
+    // Start from the current state of the universe
+    pub fn new(universe: &Universe) -> Self {
+        let (thetas, omegas) = universe.coordinates();
+        Self {
+            time: universe.time,
+            state: thetas.iter().chain(omegas.iter()).map(|&y| DoubleDouble::from(y)).collect(),
+            links: universe.balls.len(),
+            divergence: 0.0,
+            divergence_time: None,
+            earlier_divergence_time: None,
+            interrupted: false,
+        }
+    }
+
+    // Restart from the universe's current state. A divergence before the restart stays the
+    // time the simulation first left the reference.
+    pub fn restart(&self, universe: &Universe) -> Self {
+        Self { earlier_divergence_time: self.divergence_time(), ..Self::new(universe) }
+    }
+
+    // Integrate up to the universe's time with adaptive Taylor steps. Fails if the model
+    // can't be evaluated.
+    pub fn advance(&mut self, universe: &Universe) -> bool {
+        if self.interrupted {
+            return true;
+        }
+        let target = universe.time;
+        while self.time != target {
+            let Some(series) = taylor_coefficients(
+                &|t, y| universe.generic_derivative(t, y),
+                DoubleDouble::from(self.time),
+                &self.state,
+                ORDER
+            ) else {
+                return false;
+            };
+            let size = self.state.iter().fold(1.0, |max: f64, y| max.max(y.hi.abs()));
+            let h_max = taylor_step_size(&series, ORDER, TOLERANCE * size);
+
+            let remaining = target - self.time;
+            let h = if h_max >= remaining.abs() { remaining } else { h_max.copysign(remaining) };
+            if h == 0.0 || !h.is_finite() {
+                return false;
+            }
+            self.state = series
+                .iter()
+                .map(|y| y.evaluate(DoubleDouble::from(h)))
+                .collect();
+            self.time = if h == remaining { target } else { self.time + h };
+        }
+        true
+    }
+
+    // Largest difference between the f64 and the reference coordinates and their rates,
+    // noting the first time it exceeds the threshold
+    pub fn compare(&mut self, universe: &Universe, threshold: f64) {
+        if self.interrupted {
+            return;
+        }
+        let (thetas, omegas) = universe.coordinates();
+        let n = thetas.len();
+        let mut divergence: f64 = 0.0;
+        for i in 0..n {
+            let mut theta = (DoubleDouble::from(thetas[i]) - self.state[i]).value();
+            if i < self.links {
+                theta = Universe::normalize_angle(theta);
+            }
+            let omega = (DoubleDouble::from(omegas[i]) - self.state[n + i]).value();
+            divergence = divergence.max(theta.abs()).max(omega.abs());
+        }
+        self.divergence = divergence;
+        if self.divergence_time.is_none() && divergence > threshold {
+            self.divergence_time = Some(self.time);
+        }
+    }
+
+    // Stop where the chain hits something the smooth model can't follow. The run stays at
+    // that time, with the divergence it had there.
+    pub fn interrupt(&mut self) {
+        self.interrupted = true;
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted
+    }
+
+    pub fn divergence(&self) -> f64 {
+        self.divergence
+    }
+
+    // Earliest divergence since the reference run was started, through restarts
+    pub fn divergence_time(&self) -> Option<f64> {
+        self.earlier_divergence_time.or(self.divergence_time)
+    }
+
+    pub fn thetas(&self) -> Vec<f64> {
+        self.state[..self.links].iter().map(|theta| theta.value()).collect()
+    }
+
+    pub fn omegas(&self) -> Vec<f64> {
+        let n = self.state.len() / 2;
+        self.state[n..n + self.links].iter().map(|omega| omega.value()).collect()
+    }
+}
@@ -0,0 +1,154 @@
+use std::ops::{ Add, Div, Mul, Neg, Sub };
+
+/// Number type the chain's equations of motion are written over, so one model serves plain
+/// floats as well as the arithmetics used for automatic differentiation and reference runs.
+pub trait Scalar: Clone +
+    Add<Output = Self> +
+    Sub<Output = Self> +
+    Mul<Output = Self> +
+    Div<Output = Self> +
+    Neg<Output = Self> {
+    /// The number standing for the float `value`.
+    fn constant(value: f64) -> Self;
+
+    fn sin_cos(&self) -> (Self, Self);
+
+    fn sin(&self) -> Self {
+        self.sin_cos().0
+    }
+
+    fn cos(&self) -> Self {
+        self.sin_cos().1
+    }
+
+    fn sqrt(&self) -> Self;
+
+    /// The closest float, e.g. the constant term of a series.
+    fn value(&self) -> f64;
+}
+
+impl Scalar for f64 {
+    fn constant(value: f64) -> Self {
+        value
+    }
+
+    fn sin_cos(&self) -> (Self, Self) {
+        f64::sin_cos(*self)
+    }
+
+    fn sin(&self) -> Self {
+        f64::sin(*self)
+    }
+
+    fn cos(&self) -> Self {
+        f64::cos(*self)
+    }
+
+    fn sqrt(&self) -> Self {
+        f64::sqrt(*self)
+    }
+
+    fn value(&self) -> f64 {
+        *self
+    }
+}
+
+/// Dual number `value + derivative * e` with `e^2 = 0`, first-order forward-mode
+/// differentiation without the allocations of a [`Series`](crate::series::Series).
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Dual {
+    pub value: f64,
+    pub derivative: f64,
+}
+
+impl Dual {
+    pub fn new(value: f64, derivative: f64) -> Self {
+        Self { value, derivative }
+    }
+}
+
+impl Add for Dual {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.value + rhs.value, self.derivative + rhs.derivative)
+    }
+}
+
+impl Sub for Dual {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.value - rhs.value, self.derivative - rhs.derivative)
+    }
+}
+
+impl Mul for Dual {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(self.value * rhs.value, self.derivative * rhs.value + self.value * rhs.derivative)
+    }
+}
+
+impl Div for Dual {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self {
+        let value = self.value / rhs.value;
+        Self::new(value, (self.derivative - value * rhs.derivative) / rhs.value)
+    }
+}
+
+impl Neg for Dual {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self::new(-self.value, -self.derivative)
+    }
+}
+
+impl Scalar for Dual {
+    fn constant(value: f64) -> Self {
+        Self::new(value, 0.0)
+    }
+
+    fn sin_cos(&self) -> (Self, Self) {
+        let (sin, cos) = f64::sin_cos(self.value);
+        (Self::new(sin, cos * self.derivative), Self::new(cos, -sin * self.derivative))
+    }
+
+    fn sqrt(&self) -> Self {
+        let value = f64::sqrt(self.value);
+        Self::new(value, self.derivative / (2.0 * value))
+    }
+
+    fn value(&self) -> f64 {
+        self.value
+    }
+}
+
+/// Solves the `n`×`n` system `matrix * x = rhs` (`matrix` row by row) by Gaussian elimination
+/// without pivoting, which is stable for the symmetric positive definite mass matrix.
+/// Returns `None` on a zero pivot.
+pub fn solve<T: Scalar>(mut matrix: Vec<T>, mut rhs: Vec<T>) -> Option<Vec<T>> {
+    let n = rhs.len();
+    for k in 0..n {
+        let pivot = matrix[k * n + k].clone();
+        if pivot.value() == 0.0 || !pivot.value().is_finite() {
+            return None;
+        }
+        for i in k + 1..n {
+            let factor = matrix[i * n + k].clone() / pivot.clone();
+            for j in k + 1..n {
+                matrix[i * n + j] = matrix[i * n + j].clone() - factor.clone() * matrix[k * n + j].clone();
+            }
+            rhs[i] = rhs[i].clone() - factor * rhs[k].clone();
+        }
+    }
+
+    let mut x = rhs;
+    for k in (0..n).rev() {
+        let mut sum = x[k].clone();
+        for j in k + 1..n {
+            sum = sum - matrix[k * n + j].clone() * x[j].clone();
+        }
+        x[k] = sum / matrix[k * n + k].clone();
+    }
+    Some(x)
+}
@@ -0,0 +1,206 @@
+use std::ops::{ Add, Div, Mul, Neg, Sub };
+use crate::scalar::Scalar;
+
+/// Truncated power series `c_0 + c_1 * s + c_2 * s^2 + ...`, the arithmetic of Taylor-mode
+/// automatic differentiation: a function evaluated on `x + s` returns its Taylor coefficients
+/// at `x`, `c_k = f^(k)(x) / k!`. Coefficients past the end are zero, and results keep the
+/// length of the longer operand.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Series<T> {
+    pub coefficients: Vec<T>,
+}
+
+impl<T: Scalar> Series<T> {
+    pub fn new(coefficients: Vec<T>) -> Self {
+        Self { coefficients }
+    }
+
+    /// `value + s`, the variable a series is expanded in.
+    pub fn variable(value: T) -> Self {
+        Self::new(vec![value, T::constant(1.0)])
+    }
+
+    pub fn len(&self) -> usize {
+        self.coefficients.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.coefficients.is_empty()
+    }
+
+    pub fn coefficient(&self, k: usize) -> T {
+        self.coefficients.get(k).cloned().unwrap_or_else(|| T::constant(0.0))
+    }
+
+    /// Sums the series at `s` with Horner's scheme.
+    pub fn evaluate(&self, s: T) -> T {
+        self.coefficients
+            .iter()
+            .rev()
+            .fold(T::constant(0.0), |sum, c| sum * s.clone() + c.clone())
+    }
+
+    fn zip_with(self, rhs: Self, op: impl Fn(T, T) -> T) -> Self {
+        let len = self.len().max(rhs.len());
+        Self::new(
+            (0..len).map(|k| op(self.coefficient(k), rhs.coefficient(k))).collect()
+        )
+    }
+}
+
+impl<T: Scalar> Add for Series<T> {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        self.zip_with(rhs, |a, b| a + b)
+    }
+}
+
+impl<T: Scalar> Sub for Series<T> {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        self.zip_with(rhs, |a, b| a - b)
+    }
+}
+
+impl<T: Scalar> Neg for Series<T> {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self::new(self.coefficients.into_iter().map(|c| -c).collect())
+    }
+}
+
+impl<T: Scalar> Mul for Series<T> {
+    type Output = Self;
+    // Cauchy product, truncated
+    fn mul(self, rhs: Self) -> Self {
+        let len = self.len().max(rhs.len());
+        let mut product = Vec::with_capacity(len);
+        for k in 0..len {
+            let mut sum = T::constant(0.0);
+            for j in 0..=k.min(self.len().saturating_sub(1)) {
+                if k - j < rhs.len() {
+                    sum = sum + self.coefficients[j].clone() * rhs.coefficients[k - j].clone();
+                }
+            }
+            product.push(sum);
+        }
+        Self::new(product)
+    }
+}
+
+impl<T: Scalar> Div for Series<T> {
+    type Output = Self;
+    // q = a / b solves b * q = a term by term:
+    //   q_k = (a_k - sum_{j=1..k} b_j * q_{k-j}) / b_0
+    fn div(self, rhs: Self) -> Self {
+        let len = self.len().max(rhs.len());
+        let b0 = rhs.coefficient(0);
+        let mut quotient: Vec<T> = Vec::with_capacity(len);
+        for k in 0..len {
+            let mut sum = self.coefficient(k);
+            for j in 1..=k.min(rhs.len().saturating_sub(1)) {
+                sum = sum - rhs.coefficients[j].clone() * quotient[k - j].clone();
+            }
+            quotient.push(sum / b0.clone());
+        }
+        Self::new(quotient)
+    }
+}
+
+impl<T: Scalar> Scalar for Series<T> {
+    fn constant(value: f64) -> Self {
+        Self::new(vec![T::constant(value)])
+    }
+
+    // From s' = c * u' and c' = -s * u':
+    //   s_k = 1/k * sum_{j=1..k} j * u_j * c_{k-j}
+    //   c_k = -1/k * sum_{j=1..k} j * u_j * s_{k-j}
+    fn sin_cos(&self) -> (Self, Self) {
+        let (s0, c0) = self.coefficient(0).sin_cos();
+        let mut s = vec![s0];
+        let mut c = vec![c0];
+        for k in 1..self.len() {
+            let mut s_sum = T::constant(0.0);
+            let mut c_sum = T::constant(0.0);
+            for j in 1..=k {
+                let term = self.coefficients[j].clone() * T::constant(j as f64);
+                s_sum = s_sum + term.clone() * c[k - j].clone();
+                c_sum = c_sum + term * s[k - j].clone();
+            }
+            s.push(s_sum / T::constant(k as f64));
+            c.push(-c_sum / T::constant(k as f64));
+        }
+        (Self::new(s), Self::new(c))
+    }
+
+    // From r * r = u:
+    //   r_k = (u_k - sum_{j=1..k-1} r_j * r_{k-j}) / (2 * r_0)
+    fn sqrt(&self) -> Self {
+        let mut r = vec![self.coefficient(0).sqrt()];
+        let twice = r[0].clone() * T::constant(2.0);
+        for k in 1..self.len() {
+            let mut sum = self.coefficients[k].clone();
+            for j in 1..k {
+                sum = sum - r[j].clone() * r[k - j].clone();
+            }
+            r.push(sum / twice.clone());
+        }
+        Self::new(r)
+    }
+
+    fn value(&self) -> f64 {
+        self.coefficient(0).value()
+    }
+}
+
+/// Right-hand side `f(t, y)` evaluated on truncated power series.
+pub type SeriesDerivative<'a, T> = dyn Fn(&Series<T>, &[Series<T>]) -> Option<Vec<Series<T>>> + 'a;
+
+/// Taylor coefficients `y_0..=y_order` of the solution of `y' = f(t, y)` through `(t, state)`,
+/// one series per component. From `y' = f(t, y)`, `y_{k+1} = f_k(t, y_0..y_k) / (k + 1)`, so
+/// each pass through `f` adds one coefficient.
+pub fn taylor_coefficients<T: Scalar>(
+    f: &SeriesDerivative<T>,
+    t: T,
+    state: &[T],
+    order: usize
+) -> Option<Vec<Series<T>>> {
+    let mut time = Series::variable(t);
+    let mut series: Vec<Series<T>> = state
+        .iter()
+        .map(|y| Series::new(vec![y.clone()]))
+        .collect();
+    for k in 0..order {
+        // Padded with zeros so functions of time get as many coefficients as the state
+        while time.len() < k + 1 {
+            time.coefficients.push(T::constant(0.0));
+        }
+        let derivative = f(&time, &series)?;
+        for (y, f) in series.iter_mut().zip(derivative) {
+            let next = f.coefficient(k) / T::constant((k + 1) as f64);
+            if !next.value().is_finite() {
+                return None;
+            }
+            y.coefficients.push(next);
+        }
+    }
+    Some(series)
+}
+
+/// Largest magnitude of the `k`th coefficient over all components.
+pub fn largest_coefficient<T: Scalar>(series: &[Series<T>], k: usize) -> f64 {
+    series.iter().fold(0.0, |max, y| f64::max(max, y.coefficient(k).value().abs()))
+}
+
+/// Step size keeping the last two terms of series of `order` below `tolerance`, from the
+/// decay of the coefficients (Jorba & Zou). Infinite if both terms vanish.
+pub fn taylor_step_size<T: Scalar>(series: &[Series<T>], order: usize, tolerance: f64) -> f64 {
+    let mut step = f64::INFINITY;
+    for k in [order - 1, order] {
+        let size = largest_coefficient(series, k);
+        if size > 0.0 {
+            step = step.min(0.9 * f64::powf(tolerance / size, 1.0 / (k as f64)));
+        }
+    }
+    step
+}
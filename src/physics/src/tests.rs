@@ -0,0 +1,808 @@
+use std::{ cell::Cell, rc::Rc };
+use nalgebra::DVector;
+use crate::{ EnergyLimit, EventKind, Implementation, PivotMotion, Universe, Vec2 };
+use crate::integrators::{ AdamsBashforthMoulton, Derivative, Integrator };
+
+// The default double pendulum at a lively start, one time unit per frame
+fn universe(implementation: Implementation) -> Universe {
+    let mut universe = Universe::new();
+    universe.set_show_trails(false);
+    universe.set_speed(0.5);
+    universe.set_implementation(implementation);
+    universe.set_abs_tolerance(1e-11);
+    universe.set_rel_tolerance(1e-11);
+    universe.update_ball_theta(0, 1.0);
+    universe.update_ball_theta(1, 2.0);
+    universe
+}
+
+fn run(universe: &mut Universe, frames: usize) {
+    for _ in 0..frames {
+        assert_eq!(universe.time_step(1.0), 0);
+    }
+}
+
+fn energy(universe: &Universe) -> f64 {
+    universe.calculate_potential_energy() + universe.calculate_kinetic_energy()
+}
+
+fn thetas(universe: &Universe) -> Vec<f64> {
+    universe.balls.iter().map(|ball| ball.theta).collect()
+}
+
+fn distance(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).fold(0.0, |max: f64, (x, y)| max.max((x - y).abs()))
+}
+
+#[test]
+fn adaptive_steps_are_reported() {
+    let mut universe = universe(Implementation::DormandPrince);
+    run(&mut universe, 10);
+    assert!(universe.get_accepted_steps() > 0);
+    assert!(universe.get_last_step_size() > 0.0);
+}
+
+#[test]
+fn tighter_tolerances_are_more_accurate() {
+    let mut reference = universe(Implementation::DormandPrince);
+    reference.set_abs_tolerance(1e-13);
+    reference.set_rel_tolerance(1e-13);
+    run(&mut reference, 5);
+
+    let mut errors = vec![];
+    for tolerance in [1e-5, 1e-9] {
+        let mut universe = universe(Implementation::DormandPrince);
+        universe.set_abs_tolerance(tolerance);
+        universe.set_rel_tolerance(tolerance);
+        run(&mut universe, 5);
+        errors.push(distance(&thetas(&universe), &thetas(&reference)));
+    }
+    assert!(errors[1] < errors[0]);
+    assert!(errors[1] < 1e-6);
+}
+
+#[test]
+fn adaptive_steps_keep_the_energy() {
+    let mut universe = universe(Implementation::DormandPrince);
+    let initial = energy(&universe);
+    run(&mut universe, 20);
+    assert!((energy(&universe) - initial).abs() < 1e-7 * initial.abs());
+}
+
+#[test]
+fn gauss_legendre_keeps_the_energy_bounded() {
+    for implementation in [Implementation::GaussLegendre2, Implementation::GaussLegendre3] {
+        let mut universe = universe(implementation);
+        let initial = energy(&universe);
+        let mut worst: f64 = 0.0;
+        for _ in 0..100 {
+            run(&mut universe, 1);
+            worst = worst.max((energy(&universe) - initial).abs());
+        }
+        assert!(worst < 1e-6 * initial.abs());
+    }
+}
+
+#[test]
+fn more_gauss_legendre_stages_are_more_accurate() {
+    let mut reference = universe(Implementation::DormandPrince);
+    reference.set_abs_tolerance(1e-13);
+    reference.set_rel_tolerance(1e-13);
+    run(&mut reference, 5);
+
+    let mut errors = vec![];
+    for implementation in [Implementation::GaussLegendre2, Implementation::GaussLegendre3] {
+        let mut universe = universe(implementation);
+        run(&mut universe, 5);
+        errors.push(distance(&thetas(&universe), &thetas(&reference)));
+    }
+    assert!(errors[1] < errors[0]);
+    assert!(errors[0] < 1e-5);
+}
+
+// Explicit midpoint rule, counting its steps
+#[derive(Clone)]
+struct Midpoint {
+    steps: Rc<Cell<u32>>,
+}
+
+impl Integrator for Midpoint {
+    fn step(
+        &mut self,
+        f: &Derivative,
+        t: f64,
+        state: &DVector<f64>,
+        dt: f64
+    ) -> Option<DVector<f64>> {
+        self.steps.set(self.steps.get() + 1);
+        let half = state + f(t, state)? * (0.5 * dt);
+        Some(state + f(t + 0.5 * dt, &half)? * dt)
+    }
+}
+
+#[test]
+fn custom_integrators_drive_the_chain() {
+    let steps = Rc::new(Cell::new(0));
+    let mut custom = universe(Implementation::RK4);
+    custom.set_integrator(Box::new(Midpoint { steps: steps.clone() }));
+    run(&mut custom, 2);
+    assert!(steps.get() > 0);
+
+    let mut rk4 = universe(Implementation::RK4);
+    run(&mut rk4, 2);
+    assert!(distance(&thetas(&custom), &thetas(&rk4)) < 1e-3);
+
+    // Choosing a built-in implementation replaces it
+    custom.set_implementation(Implementation::RK4);
+    let before = steps.get();
+    run(&mut custom, 1);
+    assert_eq!(steps.get(), before);
+}
+
+#[test]
+fn bulirsch_stoer_meets_its_tolerance() {
+    let mut reference = universe(Implementation::DormandPrince);
+    reference.set_abs_tolerance(1e-13);
+    reference.set_rel_tolerance(1e-13);
+    run(&mut reference, 5);
+
+    let mut universe = universe(Implementation::BulirschStoer);
+    universe.set_abs_tolerance(1e-10);
+    universe.set_rel_tolerance(1e-10);
+    run(&mut universe, 5);
+    assert!(distance(&thetas(&universe), &thetas(&reference)) < 1e-7);
+    assert!(universe.get_accepted_steps() > 0);
+    assert!(universe.get_error_estimate() <= 1.0);
+}
+
+#[test]
+fn multistep_history_restarts_after_edits() {
+    let mut edited = universe(Implementation::AdamsBashforthMoulton);
+    run(&mut edited, 3);
+    edited.update_ball_theta(0, 0.5);
+
+    // A chain started from the edited state takes the same steps
+    let mut fresh = universe(Implementation::AdamsBashforthMoulton);
+    for (i, ball) in edited.balls.clone().iter().enumerate() {
+        fresh.update_ball_theta(i, ball.theta);
+        fresh.update_ball_omega(i, ball.omega);
+    }
+    run(&mut edited, 3);
+    run(&mut fresh, 3);
+    assert_eq!(thetas(&edited), thetas(&fresh));
+}
+
+#[test]
+fn adams_bashforth_moulton_follows_the_chain() {
+    let mut reference = universe(Implementation::DormandPrince);
+    reference.set_abs_tolerance(1e-13);
+    reference.set_rel_tolerance(1e-13);
+    run(&mut reference, 5);
+
+    let mut universe = universe(Implementation::AdamsBashforthMoulton);
+    run(&mut universe, 5);
+    assert!(distance(&thetas(&universe), &thetas(&reference)) < 1e-4);
+}
+
+#[test]
+fn projection_holds_the_energy_both_ways() {
+    let mut universe = universe(Implementation::Euler);
+    universe.set_limit_total_energy(true);
+    universe.set_energy_limit(EnergyLimit::Projection);
+    let initial = energy(&universe);
+    run(&mut universe, 10);
+    assert!((energy(&universe) - initial).abs() < 1e-9 * initial.abs());
+    assert!(universe.get_total_energy_correction() > 0.0);
+}
+
+#[test]
+fn scaling_never_adds_energy() {
+    let mut universe = universe(Implementation::Euler);
+    universe.set_limit_total_energy(true);
+    universe.set_energy_limit(EnergyLimit::Scale);
+    let initial = energy(&universe);
+    for _ in 0..10 {
+        run(&mut universe, 1);
+        assert!(energy(&universe) <= initial + 1e-9 * initial.abs());
+    }
+}
+
+#[test]
+fn variational_energy_oscillates_without_drift() {
+    let mut universe = universe(Implementation::Variational);
+    let initial = energy(&universe);
+    let mut worst: f64 = 0.0;
+    for _ in 0..100 {
+        run(&mut universe, 1);
+        worst = worst.max((energy(&universe) - initial).abs());
+    }
+    assert!(worst < 1e-4 * initial.abs());
+}
+
+#[test]
+fn fixed_timestep_ignores_frame_times() {
+    let mut steady = universe(Implementation::RK4);
+    let mut jittery = universe(Implementation::RK4);
+    steady.set_fixed_timestep(true);
+    jittery.set_fixed_timestep(true);
+    for frame in 0..40 {
+        steady.time_step(1.0);
+        jittery.time_step(if frame % 2 == 0 { 0.75 } else { 1.25 });
+    }
+    assert_eq!(steady.get_time(), jittery.get_time());
+    assert!(steady.balls == jittery.balls);
+}
+
+#[test]
+fn bottom_passes_come_every_half_period() {
+    let mut universe = universe(Implementation::DormandPrince);
+    universe.remove_ball();
+    universe.update_ball_theta(0, 0.05);
+    universe.set_detect_bottom_passes(true);
+    let id = universe.add_event_function(Rc::new(|_, thetas, _| thetas[0]));
+
+    let (mut passes, mut crossings) = (vec![], vec![]);
+    for _ in 0..45 {
+        run(&mut universe, 1);
+        for event in universe.get_events() {
+            match event.kind {
+                EventKind::BobBottom => passes.push(event.time),
+                EventKind::Custom if event.index == id => crossings.push(event.time),
+                _ => {}
+            }
+        }
+    }
+
+    // Small swings: T = 2 * pi * sqrt(l / g) * (1 + theta^2 / 16)
+    let period = 2.0 * std::f64::consts::PI * f64::sqrt(100.0 / 9.8) * (1.0 + 0.05 * 0.05 / 16.0);
+    assert_eq!(passes.len(), 4);
+    for pair in passes.windows(2) {
+        assert!((pair[1] - pair[0] - 0.5 * period).abs() < 1e-3);
+    }
+    assert_eq!(crossings.len(), passes.len());
+    assert!(distance(&crossings, &passes) < 1e-6);
+}
+
+#[test]
+fn reversed_time_retraces_the_run() {
+    let mut universe = universe(Implementation::GaussLegendre2);
+    let start = thetas(&universe);
+    assert!(universe.get_is_reversible());
+    assert!(universe.round_trip_error(1.0, 1e-2) < 1e-10);
+    run(&mut universe, 5);
+    universe.set_time_reversed(true);
+    run(&mut universe, 5);
+    assert!(distance(&thetas(&universe), &start) < 1e-9);
+}
+
+#[test]
+fn asymmetric_integrators_refuse_to_reverse() {
+    let mut universe = universe(Implementation::RK4);
+    assert!(!universe.get_is_reversible());
+    universe.set_time_reversed(true);
+    run(&mut universe, 1);
+    assert!(universe.get_time() > 0.0);
+}
+
+#[test]
+fn taylor_series_meets_its_tolerance() {
+    let mut reference = universe(Implementation::DormandPrince);
+    reference.set_abs_tolerance(1e-13);
+    reference.set_rel_tolerance(1e-13);
+    run(&mut reference, 5);
+
+    let mut universe = universe(Implementation::Taylor);
+    universe.set_abs_tolerance(1e-11);
+    universe.set_rel_tolerance(1e-11);
+    run(&mut universe, 5);
+    assert!(distance(&thetas(&universe), &thetas(&reference)) < 1e-8);
+    assert!(universe.get_accepted_steps() > 0);
+}
+
+#[test]
+fn reference_run_tells_accurate_runs_from_crude_ones() {
+    let mut accurate = universe(Implementation::DormandPrince);
+    accurate.start_reference();
+    run(&mut accurate, 5);
+    assert!(accurate.get_reference_divergence() < 1e-7);
+    assert_eq!(accurate.get_divergence_time(), None);
+
+    let mut crude = universe(Implementation::Euler);
+    crude.start_reference();
+    run(&mut crude, 5);
+    assert!(crude.get_reference_divergence() > 1e-3);
+    assert!(crude.get_divergence_time().is_some());
+}
+
+#[test]
+fn bdf2_damps_less_than_backward_euler() {
+    let mut losses = vec![];
+    for implementation in [Implementation::BackwardEuler, Implementation::Bdf2] {
+        let mut universe = universe(implementation);
+        universe.remove_ball();
+        let initial = energy(&universe);
+        run(&mut universe, 20);
+        let loss = initial - energy(&universe);
+        assert!(loss > 0.0);
+        losses.push(loss);
+    }
+    assert!(losses[1] < 0.01 * losses[0]);
+}
+
+#[test]
+fn enclosure_contains_the_reference() {
+    let mut universe = universe(Implementation::DormandPrince);
+    universe.remove_ball();
+    universe.start_reference();
+    universe.start_enclosure(1e-9);
+    for _ in 0..10 {
+        run(&mut universe, 1);
+        assert_eq!(universe.get_enclosure_loss_time(), None);
+        let lower = universe.get_enclosure_lower();
+        let upper = universe.get_enclosure_upper();
+        let reference: Vec<f64> = universe
+            .get_reference_thetas()
+            .into_iter()
+            .chain(universe.get_reference_omegas())
+            .collect();
+        assert_eq!(reference.len(), lower.len());
+        for (i, value) in reference.into_iter().enumerate() {
+            assert!(lower[i] <= value && value <= upper[i]);
+        }
+    }
+}
+
+// Times the bob passes straight down, over the given number of frames
+fn bottom_passes(universe: &mut Universe, frames: usize) -> Vec<f64> {
+    universe.set_detect_bottom_passes(true);
+    let mut passes = vec![];
+    for _ in 0..frames {
+        run(universe, 1);
+        for event in universe.get_events() {
+            if event.kind == EventKind::BobBottom {
+                passes.push(event.time);
+            }
+        }
+    }
+    passes
+}
+
+#[test]
+fn uniform_rods_swing_like_compound_pendulums() {
+    let mut universe = universe(Implementation::DormandPrince);
+    universe.remove_ball();
+    universe.update_ball_theta(0, 0.01);
+    universe.update_ball_mass(0, 10.0);
+    universe.update_ball_rod_mass(0, 30.0);
+    universe.set_rod_inertia(true);
+    let passes = bottom_passes(&mut universe, 30);
+
+    // I = (m + M / 3) * l^2 about the pivot, with the weight (m + M / 2) * g acting at l
+    let (m, rod, l, g) = (10.0, 30.0, 100.0, 9.8);
+    let period = 2.0 * std::f64::consts::PI * f64::sqrt(((m + rod / 3.0) * l) / ((m + rod / 2.0) * g));
+    assert!(passes.len() >= 3);
+    for pair in passes.windows(2) {
+        assert!((pair[1] - pair[0] - 0.5 * period).abs() < 1e-3);
+    }
+}
+
+#[test]
+fn dry_friction_holds_a_weak_swing() {
+    let mut universe = universe(Implementation::DormandPrince);
+    universe.remove_ball();
+    universe.set_abs_tolerance(1e-8);
+    universe.set_rel_tolerance(1e-8);
+    universe.update_ball_theta(0, 0.1);
+    // Gravity pulls with m * g * l * sin(0.1), about 980, well under the friction torque
+    universe.update_ball_coulomb_friction(0, 5000.0);
+    universe.set_stick_velocity(1e-4);
+    run(&mut universe, 20);
+    assert!((universe.balls[0].theta - 0.1).abs() < 1e-3);
+}
+
+#[test]
+fn dry_friction_slips_and_accounts_for_the_loss() {
+    let mut universe = universe(Implementation::DormandPrince);
+    universe.remove_ball();
+    universe.update_ball_theta(0, 1.0);
+    universe.update_ball_coulomb_friction(0, 100.0);
+    let start = energy(&universe);
+    run(&mut universe, 20);
+    // A sizeable share of the swing's energy above the resting bob, m * g * l * (1 - cos 1)
+    let dissipated = universe.get_dissipated_energy();
+    assert!(dissipated > 0.05 * 10.0 * 9.8 * 100.0 * (1.0 - f64::cos(1.0)));
+    assert!((energy(&universe) + dissipated - start).abs() < 1e-6 * start.abs());
+}
+
+#[test]
+fn drag_losses_balance_the_energy() {
+    let mut universe = universe(Implementation::DormandPrince);
+    universe.set_medium(1e-4, 1e-3, 0.47);
+    let start = energy(&universe);
+    run(&mut universe, 20);
+    let dissipated = universe.get_dissipated_energy();
+    assert!(dissipated > 1.0);
+    assert!((energy(&universe) + dissipated - start).abs() < 1e-6 * start.abs());
+}
+
+#[test]
+fn fast_vertical_drive_holds_the_pendulum_upright() {
+    let mut driven = universe(Implementation::DormandPrince);
+    driven.remove_ball();
+    driven.update_ball_theta(0, std::f64::consts::PI - 0.1);
+    // A^2 * w^2 = 10000 is well past 2 * g * l = 1960
+    driven.set_pivot_motion(PivotMotion::Vertical, 5.0, 20.0);
+    let start = energy(&driven);
+    let mut worst: f64 = 0.0;
+    for _ in 0..20 {
+        run(&mut driven, 1);
+        worst = worst.max((driven.balls[0].theta - std::f64::consts::PI).abs());
+    }
+    assert!(worst < 0.3);
+    assert!((energy(&driven) - start - driven.get_pivot_work()).abs() < 1e-6 * start.abs());
+
+    let mut resting = universe(Implementation::DormandPrince);
+    resting.remove_ball();
+    resting.update_ball_theta(0, std::f64::consts::PI - 0.1);
+    run(&mut resting, 20);
+    assert!((resting.balls[0].theta - std::f64::consts::PI).abs() > 0.3);
+}
+
+// Horizontal centre of mass of the cart and the bobs (the rods are massless without rod inertia)
+fn centre_of_mass_x(universe: &Universe) -> f64 {
+    let cart = universe.get_cart().unwrap();
+    let weighted = universe.balls
+        .iter()
+        .fold(cart.mass * cart.position, |sum, ball| sum + ball.mass * ball.pos.x);
+    weighted / universe.balls.iter().fold(cart.mass, |sum, ball| sum + ball.mass)
+}
+
+#[test]
+fn free_cart_keeps_the_centre_of_mass_and_the_energy() {
+    let mut universe = universe(Implementation::DormandPrince);
+    universe.enable_cart(20.0);
+    let start = energy(&universe);
+    let centre = centre_of_mass_x(&universe);
+    run(&mut universe, 20);
+    assert!(universe.get_cart().unwrap().position.abs() > 1.0);
+    assert!((centre_of_mass_x(&universe) - centre).abs() < 1e-6);
+    assert!((energy(&universe) - start).abs() < 1e-6 * start.abs());
+}
+
+#[test]
+fn springy_rods_keep_the_energy_and_their_damping_dissipates() {
+    let mut universe = universe(Implementation::DormandPrince);
+    universe.update_ball_rod_spring(0, 100.0, 50.0, 0.0);
+    universe.update_ball_rod_spring(1, 100.0, 50.0, 0.0);
+    universe.update_ball_length_rate(1, 5.0);
+    let start = energy(&universe);
+    run(&mut universe, 20);
+    assert!((universe.balls[1].rod.length - 100.0).abs() > 0.1);
+    assert!((energy(&universe) - start).abs() < 1e-6 * start.abs());
+
+    universe.update_ball_rod_spring(1, 100.0, 50.0, 5.0);
+    let start = energy(&universe);
+    run(&mut universe, 20);
+    let dissipated = universe.get_dissipated_energy();
+    assert!(dissipated > 0.0);
+    assert!((energy(&universe) + dissipated - start).abs() < 1e-6 * start.abs());
+}
+
+#[test]
+fn motor_torque_balances_gravity() {
+    let mut universe = universe(Implementation::DormandPrince);
+    universe.remove_ball();
+    universe.update_ball_theta(0, 0.3);
+    universe.update_ball_torque(0, 10.0 * 9.8 * 100.0 * f64::sin(0.3));
+    run(&mut universe, 20);
+    assert!((universe.balls[0].theta - 0.3).abs() < 1e-6);
+}
+
+#[test]
+fn actuator_work_accounts_for_the_energy_change() {
+    let mut universe = universe(Implementation::DormandPrince);
+    universe.update_ball_torque(0, 2000.0);
+    universe.set_ball_torque_schedule(1, vec![0.0, 10.0], vec![0.0, -1000.0]);
+    let start = energy(&universe);
+    run(&mut universe, 5);
+    assert!((universe.get_ball_torque(1) + 500.0).abs() < 1e-9);
+    run(&mut universe, 15);
+    let work = universe.get_actuator_work();
+    assert!(work.abs() > 100.0);
+    assert!((energy(&universe) - start - work).abs() < 1e-6 * start.abs());
+}
+
+#[test]
+fn angle_limits_bounce_the_joint_back() {
+    let mut universe = universe(Implementation::DormandPrince);
+    universe.remove_ball();
+    universe.update_ball_theta(0, 0.0);
+    universe.update_ball_omega(0, 0.5);
+    universe.update_ball_angle_limits(0, -0.2, 0.2, 1.0);
+    let start = energy(&universe);
+    for _ in 0..40 {
+        run(&mut universe, 1);
+        assert!(universe.balls[0].theta.abs() <= 0.2 + 1e-9);
+    }
+    assert!((energy(&universe) - start).abs() < 1e-6 * start.abs());
+
+    universe.update_ball_angle_limits(0, -0.2, 0.2, 0.5);
+    let start = energy(&universe);
+    run(&mut universe, 20);
+    assert!(energy(&universe) < start - 1.0);
+}
+
+#[test]
+fn tilted_gravity_moves_the_resting_angle() {
+    let mut universe = universe(Implementation::DormandPrince);
+    universe.remove_ball();
+    universe.set_gravity_vector(3.0, 9.8);
+    let resting = f64::atan2(3.0, 9.8);
+    universe.update_ball_theta(0, resting);
+    run(&mut universe, 20);
+    assert!((universe.balls[0].theta - resting).abs() < 1e-6);
+}
+
+#[test]
+fn modulated_gravity_work_accounts_for_the_energy_change() {
+    let mut universe = universe(Implementation::DormandPrince);
+    universe.set_gravity_modulation(0.3, 0.6);
+    let start = energy(&universe);
+    run(&mut universe, 20);
+    let work = universe.get_field_work();
+    assert!(work.abs() > 1.0);
+    assert!((energy(&universe) - start - work).abs() < 1e-6 * start.abs());
+}
+
+#[test]
+fn side_by_side_chains_swing_independently() {
+    let mut universe = universe(Implementation::DormandPrince);
+    universe.add_chain(300.0, 0.0, 1.0);
+    universe.add_ball_simple(2.0);
+    assert_eq!(universe.get_chain_count(), 2);
+    assert_eq!(universe.get_ball_chain(3), Some(1));
+    run(&mut universe, 10);
+    let thetas = thetas(&universe);
+    assert!(distance(&thetas[0..2], &thetas[2..4]) < 1e-6);
+    let pivot = universe.get_chain_pivots()[1];
+    assert!((pivot.x - 300.0).abs() < 1e-12 && pivot.y.abs() < 1e-12);
+    assert!((universe.balls[2].pos.x - universe.balls[0].pos.x - 300.0).abs() < 1e-4);
+}
+
+#[test]
+fn symmetric_branches_stay_mirrored() {
+    let mut universe = universe(Implementation::DormandPrince);
+    universe.remove_ball();
+    universe.update_ball_theta(0, 0.0);
+    universe.add_ball_below(0, 0.8);
+    universe.add_ball_below(0, -0.8);
+    assert_eq!(universe.get_ball_parent(2), Some(0));
+    let start = energy(&universe);
+    let mut lowest = f64::INFINITY;
+    for _ in 0..20 {
+        run(&mut universe, 1);
+        let thetas = thetas(&universe);
+        assert!(thetas[0].abs() < 1e-8);
+        assert!((thetas[1] + thetas[2]).abs() < 1e-8);
+        lowest = lowest.min(thetas[1]);
+    }
+    assert!(lowest < 0.5);
+    assert!((energy(&universe) - start).abs() < 1e-6 * start.abs());
+}
+
+#[test]
+fn upward_gravity_reads_back_signed() {
+    let mut universe = universe(Implementation::DormandPrince);
+    universe.set_gravity(-9.8);
+    assert_eq!(universe.get_gravity(), -9.8);
+    universe.set_gravity(9.8);
+    assert_eq!(universe.get_gravity(), 9.8);
+}
+
+#[test]
+fn symmetric_leapfrog_retraces_its_steps() {
+    let symmetric = universe(Implementation::SymmetricLeapfrog);
+    assert!(symmetric.get_is_reversible());
+    assert!(symmetric.round_trip_error(1.0, 1e-2) < 1e-10);
+    for implementation in [Implementation::Verlet, Implementation::Leapfrog] {
+        let explicit = universe(implementation);
+        assert!(!explicit.get_is_reversible());
+        assert!(explicit.round_trip_error(1.0, 1e-2) > 1e-10);
+    }
+}
+
+#[test]
+fn bdf2_keeps_the_energy_through_frame_jitter() {
+    let mut universe = universe(Implementation::Bdf2);
+    universe.remove_ball();
+    let start = energy(&universe);
+    for frame in 0..400 {
+        assert_eq!(universe.time_step(if frame % 2 == 0 { 1.02 } else { 0.98 }), 0);
+    }
+    assert!((energy(&universe) - start).abs() < 1e-3 * start.abs());
+}
+
+#[test]
+fn enclosure_loss_survives_edits() {
+    let mut universe = universe(Implementation::DormandPrince);
+    universe.start_enclosure(1e-6);
+    universe.set_enclosure_threshold(1e-5);
+    for _ in 0..200 {
+        if universe.get_enclosure_loss_time().is_some() {
+            break;
+        }
+        run(&mut universe, 1);
+    }
+    let lost = universe.get_enclosure_loss_time();
+    assert!(lost.is_some());
+    universe.set_medium(1e-3, 1e-3, 0.47);
+    run(&mut universe, 1);
+    assert_eq!(universe.get_enclosure_loss_time(), lost);
+}
+
+#[test]
+fn impacts_end_the_shadow_runs() {
+    let mut universe = universe(Implementation::DormandPrince);
+    universe.remove_ball();
+    universe.update_ball_theta(0, 0.0);
+    universe.update_ball_omega(0, 0.5);
+    universe.update_ball_angle_limits(0, -0.2, 0.2, 1.0);
+    universe.start_reference();
+    universe.start_enclosure(1e-9);
+    run(&mut universe, 40);
+    let loss = universe.get_enclosure_loss_time().unwrap();
+    assert!(loss > 0.0 && loss < universe.get_time());
+    assert!(universe.get_reference_interrupted());
+    assert_eq!(universe.get_divergence_time(), None);
+    assert!(universe.get_enclosure_width().is_infinite());
+}
+
+#[test]
+fn angle_limits_are_clamped_before_they_are_checked() {
+    use std::f64::consts::PI;
+    let mut universe = universe(Implementation::DormandPrince);
+    universe.update_ball_angle_limits(0, -4.0, 0.5, 1.0);
+    assert_eq!((universe.balls[0].min_angle, universe.balls[0].max_angle), (-PI, 0.5));
+    universe.update_ball_angle_limits(0, 0.5, -0.5, 1.0);
+    assert_eq!((universe.balls[0].min_angle, universe.balls[0].max_angle), (-PI, 0.5));
+    universe.update_ball_angle_limits(0, 4.0, 5.0, 1.0);
+    assert_eq!((universe.balls[0].min_angle, universe.balls[0].max_angle), (PI, PI));
+}
+
+#[test]
+fn still_air_holds_back_a_moving_pivot() {
+    let mut universe = universe(Implementation::DormandPrince);
+    universe.remove_ball();
+    universe.update_ball_theta(0, 0.0);
+    universe.set_medium(1e-3, 1e-2, 0.47);
+    // The pivot glides to the right at a steady pace, so only the air can move the bob
+    universe.set_pivot_function(
+        Rc::new(|t| (Vec2::new(20.0 * t, 0.0), Vec2::new(20.0, 0.0), Vec2::new(0.0, 0.0)))
+    );
+    let start = energy(&universe);
+    run(&mut universe, 5);
+    assert!(universe.balls[0].theta < -0.01);
+    let (dissipated, work) = (universe.get_dissipated_energy(), universe.get_pivot_work());
+    assert!(dissipated > 0.0);
+    assert!((energy(&universe) + dissipated - work - start).abs() < 1e-6 * start.abs());
+}
+
+#[test]
+fn energy_limit_scales_unless_projection_is_chosen() {
+    let universe = Universe::new();
+    assert!(universe.get_energy_limit() == EnergyLimit::Scale);
+}
+
+#[test]
+fn round_trips_run_the_bare_integrator() {
+    let mut universe = universe(Implementation::SymmetricLeapfrog);
+    universe.set_limit_total_energy(true);
+    universe.set_energy_limit(EnergyLimit::Projection);
+    universe.start_reference();
+    universe.start_enclosure(1e-9);
+    run(&mut universe, 1);
+    assert!(universe.round_trip_error(1.0, 1e-2) < 1e-10);
+}
+
+#[test]
+fn divergence_time_survives_edits() {
+    let mut universe = universe(Implementation::Euler);
+    universe.start_reference();
+    universe.set_divergence_threshold(1e-6);
+    for _ in 0..20 {
+        if universe.get_divergence_time().is_some() {
+            break;
+        }
+        run(&mut universe, 1);
+    }
+    let diverged = universe.get_divergence_time();
+    assert!(diverged.is_some());
+    universe.set_medium(1e-3, 1e-3, 0.47);
+    run(&mut universe, 1);
+    assert_eq!(universe.get_divergence_time(), diverged);
+}
+
+#[test]
+fn multistep_history_restarts_when_the_step_grows() {
+    let f: &Derivative = &|_, y| Some(DVector::from_vec(vec![y[1], -y[0]]));
+    let mut integrator = AdamsBashforthMoulton::new();
+    let (mut t, mut y) = (0.0, DVector::from_vec(vec![1.0, 0.0]));
+    let mut step = |integrator: &mut AdamsBashforthMoulton, dt: f64| {
+        y = integrator.step(f, t, &y, dt).unwrap();
+        t += dt;
+        integrator.statistics().accepted_steps
+    };
+    for _ in 0..5 {
+        step(&mut integrator, 0.1);
+    }
+    let multistep = step(&mut integrator, 0.1);
+    // A shorter step re-samples the history, a longer one bootstraps it again with RK4
+    assert_eq!(step(&mut integrator, 0.08), multistep + 1);
+    assert_eq!(step(&mut integrator, 0.1), multistep + 1);
+}
+
+#[test]
+fn gauss_legendre_halves_steps_it_cannot_solve() {
+    let mut converged = universe(Implementation::GaussLegendre2);
+    run(&mut converged, 5);
+    let mut capped = universe(Implementation::GaussLegendre2);
+    capped.set_implicit_max_iterations(4);
+    run(&mut capped, 5);
+    assert!(capped.get_rejected_steps() > 0);
+    assert!(distance(&thetas(&capped), &thetas(&converged)) < 1e-6);
+}
+
+#[test]
+fn taylor_steps_backwards_report_negative_sizes() {
+    let mut universe = universe(Implementation::Taylor);
+    assert_eq!(universe.single_physics_step(-0.5), 0);
+    let size = universe.get_last_step_size();
+    assert!((-0.5..0.0).contains(&size));
+}
+
+#[test]
+fn bdf2_restarts_when_the_step_grows() {
+    // Steps of 0.1 then one of `last`, with or without restarting the history before it
+    let after_steps = |last: f64, restart: bool| {
+        let mut universe = universe(Implementation::Bdf2);
+        for _ in 0..2 {
+            assert_eq!(universe.single_physics_step(0.1), 0);
+        }
+        if restart {
+            universe.restart_integrator();
+        }
+        assert_eq!(universe.single_physics_step(last), 0);
+        thetas(&universe)
+    };
+    assert!(distance(&after_steps(0.11, false), &after_steps(0.11, true)) > 1e-9);
+    assert_eq!(after_steps(0.13, false), after_steps(0.13, true));
+}
+
+#[test]
+fn set_gravity_keeps_the_energy_target() {
+    let mut universe = universe(Implementation::DormandPrince);
+    universe.set_medium(1e-4, 1e-3, 0.47);
+    run(&mut universe, 5);
+    let (target, dissipated) = (universe.target_energy(), universe.get_dissipated_energy());
+    universe.set_gravity(5.0);
+    assert_eq!(universe.target_energy(), target);
+    assert_eq!(universe.get_dissipated_energy(), dissipated);
+    universe.set_gravity_vector(0.0, 5.0);
+    assert_eq!(universe.get_dissipated_energy(), 0.0);
+}
+
+#[test]
+fn chains_start_from_their_moving_pivots() {
+    let mut universe = universe(Implementation::DormandPrince);
+    universe.add_chain(300.0, 0.0, 1.0);
+    universe.enable_cart(20.0);
+    universe.set_cart_velocity(5.0);
+    run(&mut universe, 3);
+    let cart = universe.get_cart().unwrap().position;
+    let first = universe.get_ball_pivot(0).unwrap();
+    let second = universe.get_ball_pivot(2).unwrap();
+    assert!(cart.abs() > 1.0);
+    assert!((first.x - cart).abs() < 1e-12 && first.y.abs() < 1e-12);
+    assert!((second.x - cart - 300.0).abs() < 1e-12 && second.y.abs() < 1e-12);
+    assert!(universe.get_ball_pivot(1).is_none());
+}
@@ -0,0 +1,178 @@
+use serde::{ Serialize, Deserialize };
+use crate::interval::Interval;
+use crate::series::{ taylor_coefficients, taylor_step_size, Series };
+use crate::Universe;
+
+// Taylor order of the enclosure steps, and the truncation error the steps are sized for
+const ORDER: usize = 12;
+const STEP_TOLERANCE: f64 = 1e-12;
+// Tries at the a-priori enclosure before a step is halved, and halvings before giving up
+const PICARD_ITERATIONS: usize = 8;
+const MAX_HALVINGS: usize = 30;
+
+// Rigorous enclosure of the chain state, carried alongside the simulation with Moore's
+// interval Taylor method. Each step of size h from the enclosure Y first finds an a-priori
+// enclosure B of every solution over [t, t + h] by Picard iteration, Y + [0, h] * f(B) ⊆ B,
+// then sums the Taylor polynomial over Y and bounds the remainder over B:
+//   Y(t + h) ⊆ sum_{k<K} y_k(Y) * h^k + y_K(B) * h^K
+// The enclosure grows from the initial uncertainty, the wrapping effect of boxes and the
+// stretching of the dynamics; once it is wider than the threshold the run stops.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Enclosure {
+    time: f64,
+    state: Vec<Interval>, // [thetas; omegas], with the cart after the angles
+    initial_width: f64,
+    width: f64,
+    loss_time: Option<f64>,
+    earlier_loss_time: Option<f64>, // Loss of a run before the last restart
+}
+
+impl Enclosure {
+    // Boxes of initial_width around the current state of the universe
+    pub fn new(universe: &Universe, initial_width: f64) -> Self {
+        let (thetas, omegas) = universe.coordinates();
+        Self {
+            time: universe.time,
+            state: thetas
+                .iter()
+                .chain(omegas.iter())
+                .map(|&value| Interval::point(value).inflated(0.5 * initial_width))
+                .collect(),
+            initial_width,
+            width: initial_width,
+            loss_time: None,
+            earlier_loss_time: None,
+        }
+    }
+
+    // Restart from the universe's current state with the same initial width. A loss before
+    // the restart stays the time the simulation stopped being trusted.
+    pub fn restart(&self, universe: &Universe) -> Self {
+        Self { earlier_loss_time: self.loss_time(), ..Self::new(universe, self.initial_width) }
+    }
+
+    // Carry the enclosure to the universe's time, unless it was already lost
+    pub fn advance(&mut self, universe: &Universe, threshold: f64) {
+        let target = universe.time;
+        while self.loss_time.is_none() && self.time != target {
+            let remaining = target - self.time;
+            let mut h = self.step_size(universe).min(remaining.abs()).copysign(remaining);
+            let mut next = None;
+            for _ in 0..MAX_HALVINGS {
+                next = self.step(universe, h);
+                if next.is_some() {
+                    break;
+                }
+                h *= 0.5;
+            }
+
+            match next {
+                Some(state) => {
+                    self.state = state;
+                    self.time = if h == remaining { target } else { self.time + h };
+                    self.width = self.state.iter().fold(0.0, |max: f64, y| max.max(y.width()));
+                    if self.width.is_nan() || self.width > threshold {
+                        self.loss_time = Some(self.time);
+                    }
+                }
+                None => {
+                    // No step verifies any more
+                    self.width = f64::INFINITY;
+                    self.loss_time = Some(self.time);
+                }
+            }
+        }
+    }
+
+    // Give up where the chain hits something the smooth model can't follow: the boxes no
+    // longer bound it past there
+    pub fn interrupt(&mut self) {
+        self.width = f64::INFINITY;
+        self.loss_time.get_or_insert(self.time);
+    }
+
+    // Step size from the Taylor coefficients at the midpoints of the boxes
+    fn step_size(&self, universe: &Universe) -> f64 {
+        let midpoints: Vec<f64> = self.state.iter().map(|y| y.midpoint()).collect();
+        let size = midpoints.iter().fold(1.0, |max: f64, y| max.max(y.abs()));
+        taylor_coefficients(
+            &|t, y: &[Series<f64>]| universe.generic_derivative(t, y),
+            self.time,
+            &midpoints,
+            ORDER
+        ).map_or(0.0, |series| taylor_step_size(&series, ORDER, STEP_TOLERANCE * size))
+    }
+
+    fn step(&self, universe: &Universe, h: f64) -> Option<Vec<Interval>> {
+        if h == 0.0 || !h.is_finite() {
+            return None;
+        }
+        let f = |t: &Series<Interval>, y: &[Series<Interval>]| universe.generic_derivative(t, y);
+        let times = Interval::new(self.time, self.time + h);
+        let steps = Interval::new(0.0, h);
+
+        // A-priori enclosure over the whole step
+        let picard = |b: &[Interval]| -> Option<Vec<Interval>> {
+            let derivative = universe.generic_derivative(&times, b)?;
+            Some(
+                self.state
+                    .iter()
+                    .zip(derivative)
+                    .map(|(y, f)| *y + steps * f)
+                    .collect()
+            )
+        };
+        let mut bound: Vec<Interval> = picard(&self.state)?
+            .iter()
+            .map(|b| b.inflated(0.1 * b.width() + 1e-12 * (1.0 + b.midpoint().abs())))
+            .collect();
+        let mut verified = false;
+        for _ in 0..PICARD_ITERATIONS {
+            let candidate = picard(&bound)?;
+            if candidate.iter().zip(&bound).all(|(c, b)| c.is_subset_of(b)) {
+                bound = candidate;
+                verified = true;
+                break;
+            }
+            bound = candidate
+                .iter()
+                .zip(&bound)
+                .map(|(c, b)| {
+                    let hull = c.hull(b);
+                    hull.inflated(0.1 * hull.width())
+                })
+                .collect();
+        }
+        if !verified {
+            return None;
+        }
+
+        let series = taylor_coefficients(&f, Interval::point(self.time), &self.state, ORDER - 1)?;
+        let remainder = taylor_coefficients(&f, times, &bound, ORDER)?;
+        let h = Interval::point(h);
+        let h_order = (0..ORDER).fold(Interval::point(1.0), |power, _| power * h);
+        let next: Vec<Interval> = series
+            .iter()
+            .zip(&remainder)
+            .map(|(y, r)| y.evaluate(h) + r.coefficient(ORDER) * h_order)
+            .collect();
+        next.iter().all(|y| y.lo.is_finite() && y.hi.is_finite()).then_some(next)
+    }
+
+    pub fn width(&self) -> f64 {
+        self.width
+    }
+
+    // Earliest loss since the enclosure was started, through restarts
+    pub fn loss_time(&self) -> Option<f64> {
+        self.earlier_loss_time.or(self.loss_time)
+    }
+
+    pub fn lower(&self) -> Vec<f64> {
+        self.state.iter().map(|y| y.lo).collect()
+    }
+
+    pub fn upper(&self) -> Vec<f64> {
+        self.state.iter().map(|y| y.hi).collect()
+    }
+}